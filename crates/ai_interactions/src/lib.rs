@@ -1,9 +1,12 @@
-use rust_parsing::{ErrorHandling, error::InvalidIoOperationsSnafu};
-use snafu::ResultExt;
+use rust_parsing::{
+    ErrorHandling,
+    error::{InvalidIoOperationsSnafu, InvalidSettingSnafu},
+};
+use snafu::{OptionExt, ResultExt};
 use std::fs;
 use std::path::Path;
 use tracing::{Level, event};
-use yaml_rust2::{Yaml, YamlLoader};
+use yaml_rust2::{Yaml, YamlLoader, yaml::Hash};
 
 #[derive(Debug)]
 pub struct LLMSettings {
@@ -19,11 +22,23 @@ pub struct PathdogSettings {
     pub excluded_functions: Vec<String>,
     pub affected_object_types: Vec<String>,
     pub llm_model: String,
+    pub public_api_only: bool,
+    pub include_tests: bool,
+    pub context_budget: usize,
+    pub context_rendering: String,
+    pub full_body_threshold: usize,
+    pub proc_macro_server: bool,
+    pub load_build_scripts: bool,
+    pub analyzer_timeout_secs: usize,
+    pub dependency_docs: bool,
+    pub dependency_docs_budget: usize,
+    pub macro_expansion_budget: usize,
 }
 
 #[derive(Debug)]
 pub struct YamlRead {
     pub prompt: String,
+    pub macro_prompt: String,
     pub llm_settings: LLMSettings,
     pub patchdog_settings: PathdogSettings,
 }
@@ -48,6 +63,11 @@ pub fn return_prompt() -> Result<YamlRead, ErrorHandling> {
                 .get(&Yaml::String("prompt".into()))
                 .and_then(|v| v.as_str().map(String::from))
                 .unwrap_or_default();
+            //macro_rules! definitions fall back to the regular prompt when no variant is configured
+            let macro_prompt = h
+                .get(&Yaml::String("macro_prompt".into()))
+                .and_then(|v| v.as_str().map(String::from))
+                .unwrap_or_else(|| prompt.clone());
             let llm_settings = h
                 .get(&Yaml::String("LLM_settings".into()))
                 .map(|v| v.as_hash().unwrap())
@@ -103,9 +123,47 @@ pub fn return_prompt() -> Result<YamlRead, ErrorHandling> {
                 .get(&Yaml::String("llm_model".into()))
                 .and_then(|v| v.as_str().map(String::from))
                 .unwrap_or_default();
+            let public_api_only = patchdog_settings
+                .get(&Yaml::String("public_api_only".into()))
+                .and_then(|v| v.as_bool())
+                .unwrap_or_default();
+            let include_tests = patchdog_settings
+                .get(&Yaml::String("include_tests".into()))
+                .and_then(|v| v.as_bool())
+                .unwrap_or_default();
+            //Without a budget the gathered context is sent untrimmed
+            let context_budget =
+                usize_setting(patchdog_settings, "context_budget")?.unwrap_or(usize::MAX);
+            let context_rendering = patchdog_settings
+                .get(&Yaml::String("context_rendering".into()))
+                .and_then(|v| v.as_str().map(String::from))
+                .unwrap_or_default();
+            let full_body_threshold =
+                usize_setting(patchdog_settings, "full_body_threshold")?.unwrap_or_default();
+            let proc_macro_server = patchdog_settings
+                .get(&Yaml::String("proc_macro_server".into()))
+                .and_then(|v| v.as_bool())
+                .unwrap_or_default();
+            let load_build_scripts = patchdog_settings
+                .get(&Yaml::String("load_build_scripts".into()))
+                .and_then(|v| v.as_bool())
+                .unwrap_or_default();
+            //0 lets the workspace load for as long as it takes
+            let analyzer_timeout_secs =
+                usize_setting(patchdog_settings, "analyzer_timeout_secs")?.unwrap_or_default();
+            let dependency_docs = patchdog_settings
+                .get(&Yaml::String("dependency_docs".into()))
+                .and_then(|v| v.as_bool())
+                .unwrap_or_default();
+            let dependency_docs_budget =
+                usize_setting(patchdog_settings, "dependency_docs_budget")?.unwrap_or_default();
+            //0 leaves macro calls unexpanded
+            let macro_expansion_budget =
+                usize_setting(patchdog_settings, "macro_expansion_budget")?.unwrap_or_default();
 
             Ok(YamlRead {
                 prompt,
+                macro_prompt,
                 llm_settings: LLMSettings {
                     openai_model,
                     gemini_model,
@@ -117,6 +175,17 @@ pub fn return_prompt() -> Result<YamlRead, ErrorHandling> {
                     excluded_functions,
                     affected_object_types,
                     llm_model,
+                    public_api_only,
+                    include_tests,
+                    context_budget,
+                    context_rendering,
+                    full_body_threshold,
+                    proc_macro_server,
+                    load_build_scripts,
+                    analyzer_timeout_secs,
+                    dependency_docs,
+                    dependency_docs_budget,
+                    macro_expansion_budget,
                 },
             })
         } else {
@@ -127,6 +196,7 @@ pub fn return_prompt() -> Result<YamlRead, ErrorHandling> {
             );
             Ok(YamlRead {
                 prompt: "".to_string(),
+                macro_prompt: "".to_string(),
                 llm_settings: LLMSettings {
                     openai_model: "".to_string(),
                     gemini_model: "".to_string(),
@@ -138,6 +208,17 @@ pub fn return_prompt() -> Result<YamlRead, ErrorHandling> {
                     excluded_functions: vec![],
                     affected_object_types: vec![],
                     llm_model: String::new(),
+                    public_api_only: false,
+                    include_tests: false,
+                    context_budget: usize::MAX,
+                    context_rendering: String::new(),
+                    full_body_threshold: 0,
+                    proc_macro_server: false,
+                    load_build_scripts: false,
+                    analyzer_timeout_secs: 0,
+                    dependency_docs: false,
+                    dependency_docs_budget: 0,
+                    macro_expansion_budget: 0,
                 },
             })
         }
@@ -150,6 +231,7 @@ pub fn return_prompt() -> Result<YamlRead, ErrorHandling> {
         // Default config if YAML isn't structured properly
         Ok(YamlRead {
             prompt: "".to_string(),
+            macro_prompt: "".to_string(),
             llm_settings: LLMSettings {
                 openai_model: "".to_string(),
                 gemini_model: "".to_string(),
@@ -161,7 +243,39 @@ pub fn return_prompt() -> Result<YamlRead, ErrorHandling> {
                 excluded_functions: vec![],
                 affected_object_types: vec![],
                 llm_model: String::new(),
+                public_api_only: false,
+                include_tests: false,
+                context_budget: usize::MAX,
+                context_rendering: String::new(),
+                full_body_threshold: 0,
+                proc_macro_server: false,
+                load_build_scripts: false,
+                analyzer_timeout_secs: 0,
+                dependency_docs: false,
+                dependency_docs_budget: 0,
+                macro_expansion_budget: 0,
             },
         })
     }
 }
+
+/// Reads the integer setting `key`, which counts a size or a duration and can't be negative.
+///
+/// # Arguments
+/// * `settings` - The `Patchdog_settings` table of the configuration.
+/// * `key` - The name of the setting.
+///
+/// # Returns
+/// A `Result<Option<usize>, ErrorHandling>` with the value, `None` if the setting isn't given, or `ErrorHandling::InvalidSetting`
+/// if it is negative.
+fn usize_setting(settings: &Hash, key: &str) -> Result<Option<usize>, ErrorHandling> {
+    settings
+        .get(&Yaml::String(key.into()))
+        .and_then(Yaml::as_i64)
+        .map(|value| {
+            usize::try_from(value)
+                .ok()
+                .context(InvalidSettingSnafu { key, value })
+        })
+        .transpose()
+}
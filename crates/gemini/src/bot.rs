@@ -6,44 +6,45 @@ use std::env::var;
 
 #[allow(async_fn_in_trait)]
 pub trait RequestResponseConstruction {
-    async fn switch_llm(file_content: &str) -> Result<String, ErrorHandling>;
-    async fn call_llm_gemini(file_content: &str) -> Result<String, ErrorHandling>;
-    async fn call_llm_openai(file_content: &str) -> Result<String, ErrorHandling>;
+    async fn switch_llm(file_content: &str, prompt: &str) -> Result<String, ErrorHandling>;
+    async fn call_llm_gemini(file_content: &str, prompt: &str) -> Result<String, ErrorHandling>;
+    async fn call_llm_openai(file_content: &str, prompt: &str) -> Result<String, ErrorHandling>;
 }
 
 pub struct AiRequest;
 
 impl RequestResponseConstruction for AiRequest {
-    async fn switch_llm(file_content: &str) -> Result<String, ErrorHandling> {
+    async fn switch_llm(file_content: &str, prompt: &str) -> Result<String, ErrorHandling> {
         let yaml = return_prompt()?;
         let model = yaml.patchdog_settings.llm_model.as_str();
         match model {
-            "openai" => AiRequest::call_llm_openai(file_content).await,
-            "google" => AiRequest::call_llm_gemini(file_content).await,
+            "openai" => AiRequest::call_llm_openai(file_content, prompt).await,
+            "google" => AiRequest::call_llm_gemini(file_content, prompt).await,
             _ => Ok(format!("Specified model {} is not supported", model)),
         }
     }
 
-    async fn call_llm_gemini(file_content: &str) -> Result<String, ErrorHandling> {
+    async fn call_llm_gemini(file_content: &str, prompt: &str) -> Result<String, ErrorHandling> {
         let api_key = var("API_KEY_GEMINI")?;
         let model = return_prompt()?.llm_settings.gemini_model;
         let client = Gemini::with_model(api_key, model)
             .generate_content()
-            .with_system_prompt(return_prompt()?.prompt)
+            .with_system_prompt(prompt)
             .with_user_message(file_content)
             .execute()
             .await?;
         Ok(client.text())
     }
 
-    /// Asynchronously sends a code snippet (`file_content`) to the OpenAI Large Language Model for processing. It retrieves the OpenAI API key from the `API_KEY_OPENAI` environment variable and configures the chat model and initial prompt based on the application's settings.
+    /// Asynchronously sends a code snippet (`file_content`) to the OpenAI Large Language Model for processing. It retrieves the OpenAI API key from the `API_KEY_OPENAI` environment variable and configures the chat model based on the application's settings, sending `prompt` as the initial message.
     ///
     /// # Arguments
     /// * `file_content` - A string slice containing the code or text to be sent as a user message to the OpenAI LLM.
+    /// * `prompt` - The instructions sent ahead of `file_content`, either the function or the `macro_rules!` prompt.
     ///
     /// # Returns
     /// A `Result<String, ErrorHandling>` containing the text response from the OpenAI LLM on success, or an `ErrorHandling` if the API key is missing, configuration cannot be loaded, or the API call fails.
-    async fn call_llm_openai(file_content: &str) -> Result<String, ErrorHandling> {
+    async fn call_llm_openai(file_content: &str, prompt: &str) -> Result<String, ErrorHandling> {
         let api_key = var("API_KEY_OPENAI")?;
         let client = Client::new(&api_key);
         let args = openai_rust::chat::ChatArguments::new(
@@ -51,7 +52,7 @@ impl RequestResponseConstruction for AiRequest {
             vec![
                 openai_rust::chat::Message {
                     role: "user".to_owned(),
-                    content: return_prompt()?.prompt.to_string(),
+                    content: prompt.to_string(),
                 },
                 openai_rust::chat::Message {
                     role: "user".to_owned(),
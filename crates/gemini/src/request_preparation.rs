@@ -1,7 +1,7 @@
 use ai_interactions::return_prompt;
-use rust_parsing::ErrorHandling;
+use rust_parsing::rust_parser::{FunctionSignature, MacroDefinition};
+use rust_parsing::{ErrorHandling, Span};
 use serde::{Deserialize, Serialize};
-use std::ops::Range;
 use std::path::PathBuf;
 use std::{fmt::Display, time};
 use tracing::{Level, event};
@@ -57,6 +57,10 @@ Currently, inclusion of this information is not in the scope.
 pub struct SingleFunctionData {
     pub fn_name: String,
     pub function_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<FunctionSignature>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub macro_rules: Option<MacroDefinition>,
     pub context: Context,
     #[serde(skip_serializing)]
     pub metadata: Metadata,
@@ -65,14 +69,28 @@ pub struct SingleFunctionData {
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct Context {
     pub class_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_doc: Option<String>,
     pub external_dependencies: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usages: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub test_examples: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trait_declaration: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub module: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module_context: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub macro_expansions: Vec<String>,
     pub old_comment: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct Metadata {
     pub filepath: PathBuf,
-    pub line_range: Range<usize>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -81,7 +99,7 @@ pub struct RequestToAgent {
 } //Req Res = Request Response
 
 impl Context {
-    /// Calculates an estimated "size" for the current `Context` by summing the character lengths of all strings in its `external_dependencies` and `old_comment` vectors. This size metric is likely used to approximate token usage for LLM requests, helping to manage input limits.
+    /// Calculates an estimated "size" for the current `Context` by summing the character lengths of all strings in its `external_dependencies`, `usages`, `test_examples`, `macro_expansions` and `old_comment` vectors, its `class_doc`, `trait_declaration` and `module_context`. This size metric is likely used to approximate token usage for LLM requests, helping to manage input limits.
     ///
     /// # Returns
     /// A `usize` representing the combined length of all dependency and comment strings, serving as a heuristic for content size.
@@ -90,6 +108,17 @@ impl Context {
         for each in &self.external_dependencies {
             size_ext += each.len();
         }
+        for each in self
+            .usages
+            .iter()
+            .chain(&self.test_examples)
+            .chain(&self.macro_expansions)
+            .chain(&self.class_doc)
+            .chain(&self.trait_declaration)
+            .chain(&self.module_context)
+        {
+            size_ext += each.len();
+        }
         for each in &self.old_comment {
             size_ext += each.len();
         }
@@ -99,7 +128,22 @@ impl Context {
 
 impl SingleFunctionData {
     pub fn size(&self) -> usize {
-        (self.fn_name.len() + self.context.size() + self.function_text.len()) / 3 //One token is approx. 3 symbols
+        let signature_size = self
+            .signature
+            .as_ref()
+            .and_then(|signature| serde_json::to_string(signature).ok())
+            .map_or(0, |signature| signature.len());
+        let macro_size = self
+            .macro_rules
+            .as_ref()
+            .and_then(|macro_rules| serde_json::to_string(macro_rules).ok())
+            .map_or(0, |macro_rules| macro_rules.len());
+        (self.fn_name.len()
+            + signature_size
+            + macro_size
+            + self.context.size()
+            + self.function_text.len())
+            / 3 //One token is approx. 3 symbols
     }
 }
 
@@ -107,6 +151,8 @@ impl SingleFunctionData {
 pub struct MappedRequest {
     pub remaining_capacity: usize,
     pub data: Vec<Request>,
+    //Batches of macro_rules! definitions are sent with macro_prompt instead of prompt
+    pub is_macro: bool,
 }
 
 impl MappedRequest {
@@ -119,17 +165,29 @@ impl MappedRequest {
             remaining_capacity: return_prompt()?.llm_settings.tokens
                 / return_prompt()?.llm_settings.requests,
             data: Vec::<Request>::new(),
+            is_macro: false,
         })
     }
 
     /// Attempts to add a `Request` (containing `SingleFunctionData`) to the current `PreparingRequests` batch. It first checks if the `request_data`'s size fits within the `remaining_capacity` of the batch. If it fits, the request is added, and the capacity is updated.
+    /// A `module_context` already carried by an earlier request of the same module in the batch is dropped before measuring, so each module is paid for once per batch.
     ///
     /// # Arguments
     /// * `request_data` - The `Request` object, which includes `SingleFunctionData`, to be added to the batch.
     ///
     /// # Returns
     /// `true` if the `request_data` was successfully added to the batch, `false` otherwise (e.g., if it exceeds the remaining capacity).
-    pub fn function_add(&mut self, request_data: Request) -> bool {
+    pub fn function_add(&mut self, mut request_data: Request) -> bool {
+        //The context of a module is sent once per batch, with the first request coming from it
+        let context = &request_data.data.context;
+        if !context.module.is_empty()
+            && self.data.iter().any(|each| {
+                each.data.context.module == context.module
+                    && each.data.context.module_context.is_some()
+            })
+        {
+            request_data.data.context.module_context = None;
+        }
         let size = request_data.data.size();
         if size <= self.remaining_capacity {
             self.data.push(request_data);
@@ -274,10 +332,16 @@ impl RequestToAgent {
     pub async fn send_batches(request: &Vec<WaitForTimeout>) -> Result<Vec<String>, ErrorHandling> {
         let mut response = vec![];
         let one_minute = time::Duration::from_secs(61);
+        let config = return_prompt()?;
         for single_request in request {
             for each in &single_request.prepared_requests {
                 let as_json = serde_json::to_string_pretty(&each.data)?;
-                match AiRequest::switch_llm(&as_json).await {
+                let prompt = if each.is_macro {
+                    &config.macro_prompt
+                } else {
+                    &config.prompt
+                };
+                match AiRequest::switch_llm(&as_json, prompt).await {
                     //Handling exclusive case, where one of the requests may fail
                     Ok(r) => {
                         response.push(r);
@@ -354,6 +418,7 @@ impl RequestToAgent {
     }
 
     /// Organizes a vector of individual `Request` objects into multiple `MappedRequest` batches, respecting capacity limits. It iteratively adds requests to a current `MappedRequest`. If a request exceeds the current capacity, the filled `MappedRequest` is finalized, a new one is started, and the process continues.
+    /// Requests for `macro_rules!` definitions are batched separately from functions, as they are sent with a different prompt.
     ///
     /// # Arguments
     /// * `request` - A `Vec<Request>` containing the individual requests to be batched.
@@ -364,28 +429,42 @@ impl RequestToAgent {
         &mut self,
         request: Vec<Request>,
     ) -> Result<Vec<MappedRequest>, ErrorHandling> {
-        let mut batches: Vec<MappedRequest> = Vec::new();
-        let mut mapped_requests = MappedRequest::new()?;
-        for data in request {
-            if !mapped_requests.function_add(data.clone()) {
-                //Preserving overflow of preparing request to next iter
-                if !mapped_requests.data.is_empty() {
-                    batches.push(mapped_requests);
-                }
-                //Reinitializing preparing_requests to free the buffer
-                mapped_requests = MappedRequest::new()?;
+        let (macros, functions): (Vec<Request>, Vec<Request>) = request
+            .into_iter()
+            .partition(|each| each.data.macro_rules.is_some());
+        let mut batches = batch_requests(functions, false)?;
+        batches.extend(batch_requests(macros, true)?);
+        Ok(batches)
+    }
+}
 
-                // Attempt to push
-                if !mapped_requests.function_add(data) {
-                    // Here should be handled the case, where single object exceeds token limit
-                    //Which is likely would not be possible
-                }
+fn batch_requests(
+    request: Vec<Request>,
+    is_macro: bool,
+) -> Result<Vec<MappedRequest>, ErrorHandling> {
+    let mut batches: Vec<MappedRequest> = Vec::new();
+    let mut mapped_requests = MappedRequest::new()?;
+    mapped_requests.is_macro = is_macro;
+    for data in request {
+        if !mapped_requests.function_add(data.clone()) {
+            //Preserving overflow of preparing request to next iter
+            if !mapped_requests.data.is_empty() {
+                batches.push(mapped_requests);
+            }
+            //Reinitializing preparing_requests to free the buffer
+            mapped_requests = MappedRequest::new()?;
+            mapped_requests.is_macro = is_macro;
+
+            // Attempt to push
+            if !mapped_requests.function_add(data) {
+                // Here should be handled the case, where single object exceeds token limit
+                //Which is likely would not be possible
             }
         }
-        // Last unempty request
-        if !mapped_requests.data.is_empty() {
-            batches.push(mapped_requests);
-        }
-        Ok(batches)
     }
+    // Last unempty request
+    if !mapped_requests.data.is_empty() {
+        batches.push(mapped_requests);
+    }
+    Ok(batches)
 }
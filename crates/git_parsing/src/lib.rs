@@ -1,3 +1,5 @@
 pub mod patch_parse;
+pub mod span;
 // Re-exporting to make it available externally
 pub use patch_parse::*;
+pub use span::{FileId, LineCol, LineIndex, Span};
@@ -1,3 +1,4 @@
+use crate::span::{FileId, LineIndex, Span};
 use git2::{Diff, Patch};
 use rayon::prelude::*;
 use snafu::{OptionExt, Snafu};
@@ -28,10 +29,12 @@ pub enum ChangeType {
     Remove,
     Modify,
 }
+/// A line added or kept by a patch, located in the new version of its file.
+/// `span` covers the whole line without its line break, in the same representation as parsed items.
 #[derive(Clone, Hash, Eq, PartialEq, Debug)]
 pub struct Hunk {
     pub change: ChangeType,
-    pub line: usize,
+    pub span: Span,
     pub filename: String,
 }
 
@@ -39,48 +42,54 @@ impl Hunk {
     pub fn filename(&self) -> String {
         self.filename.to_owned()
     }
-    pub fn get_line(&self) -> usize {
-        self.line
-    }
 }
 
+/// A line of a patch as git reports it, before it is located in its file.
+#[derive(Clone, Debug)]
+struct ChangedLine {
+    change: ChangeType,
+    line: usize,
+    filename: String,
+}
+
+/// Lists the `.rs` files changed by a patch, as paths relative to the root of the repository.
+///
+/// # Arguments
+/// * `relative_path` - The root of the repository the paths of the patch are relative to.
+/// * `patch_src` - A reference to the `git2::Diff` object representing the Git patch.
+///
+/// # Returns
+/// A `Result<Vec<String>, Git2ErrorHandling>` with the path of every changed Rust file, once each.
 pub fn match_patch_with_parse(
     relative_path: &Path,
     patch_src: &Diff<'static>,
-) -> Result<Vec<Hunk>, Git2ErrorHandling> {
+) -> Result<Vec<String>, Git2ErrorHandling> {
     let list_of_unique_files = read_non_repeting_functions(patch_src, relative_path)?;
     let changed = get_filenames(patch_src)?;
-    let mut hunks = git_get_hunks(patch_src, changed)?;
-    hunks.sort_by_key(|a| a.filename());
+    let mut lines = git_get_hunks(patch_src, changed)?;
+    lines.sort_by_key(|a| a.filename.to_owned());
     Ok(list_of_unique_files
         .par_iter()
-        .map(|each_unique| {
-            let collected = hunks.par_iter().find_first(|each| {
-                let full_path = relative_path.join(each.filename());
-                full_path == Path::new(&each_unique)
-            });
-            if let Some(collected) = collected {
-                collected.to_owned()
-            } else {
-                //Here returning Remove, as it is mitigated afterwards
-                Hunk {
-                    change: ChangeType::Remove,
-                    line: 0,
-                    filename: String::new(),
-                }
-            }
+        .filter_map(|each_unique| {
+            lines
+                .par_iter()
+                .find_first(|each| relative_path.join(&each.filename) == *each_unique)
+                .map(|each| each.filename.to_owned())
         })
-        .collect::<Vec<Hunk>>())
+        .collect::<Vec<String>>())
 }
 
 /// Extracts hunks from a Git patch that correspond to a specific file path.
 /// It first gets all filenames and hunks from the patch, then filters these hunks to include only those belonging to the specified `at_file_path`.
-/// The resulting vector of hunks is sorted by filename.
+/// Git only reports line numbers, so every line is located in the new contents of the file through `line_index`, which gives each hunk its `Span`.
+/// Lines the file doesn't have, e.g. because it changed since the patch was made, are left out.
 ///
 /// # Arguments
 ///
 /// * `patch_src` - A reference to a `git2::Diff` object representing the Git patch.
 /// * `at_file_path` - A string slice (`&str`) representing the file path for which to retrieve hunks.
+/// * `file` - The `FileId` the file was given for this run.
+/// * `line_index` - The `LineIndex` of the current contents of the file.
 ///
 /// # Returns
 ///
@@ -90,15 +99,22 @@ pub fn match_patch_with_parse(
 pub fn get_easy_hunk(
     patch_src: &Diff<'static>,
     at_file_path: &str,
+    file: FileId,
+    line_index: &LineIndex,
 ) -> Result<Vec<Hunk>, Git2ErrorHandling> {
     let mut vec_of_hunks: Vec<Hunk> = Vec::new();
     let filenames = get_filenames(patch_src)?;
-    let hunks = git_get_hunks(patch_src, filenames)?;
-    vec_of_hunks.sort_by_key(|hunk| hunk.filename.to_owned());
+    let lines = git_get_hunks(patch_src, filenames)?;
 
-    for hunk in hunks {
-        if hunk.filename() == at_file_path {
-            vec_of_hunks.push(hunk);
+    for line in lines {
+        if line.filename == at_file_path
+            && let Some(span) = line_index.line_span(file, line.line)
+        {
+            vec_of_hunks.push(Hunk {
+                change: line.change,
+                span,
+                filename: line.filename,
+            });
         }
     }
     Ok(vec_of_hunks)
@@ -117,19 +133,19 @@ fn get_filenames(diff: &Diff<'static>) -> Result<Vec<String>, Git2ErrorHandling>
     Ok(vector_of_filenames)
 }
 
-/// Extracts and processes individual hunks (code changes) from a Git diff object. It iterates through the deltas, creates a patch for each, and then examines every line within each hunk to identify additions or modifications. This data is then structured into `Hunk` objects.
+/// Extracts and processes individual hunks (code changes) from a Git diff object. It iterates through the deltas, creates a patch for each, and then examines every line within each hunk to identify additions or modifications. This data is then structured into `ChangedLine` objects.
 ///
 /// # Arguments
 /// * `diff` - A reference to the `git2::Diff` object representing the Git changes.
 /// * `vector_of_filenames` - A `Vec<String>` providing the filenames corresponding to the deltas, used to associate hunks with their respective files.
 ///
 /// # Returns
-/// A `Result<Vec<Hunk>, Git2ErrorHandling>` containing a vector of `Hunk` structs on success, detailing the change type, line number, and filename for each modification, or a `Git2ErrorHandling` if parsing the diff or patch fails.
+/// A `Result<Vec<ChangedLine>, Git2ErrorHandling>` containing a vector of `ChangedLine` structs on success, detailing the change type, line number, and filename for each modification, or a `Git2ErrorHandling` if parsing the diff or patch fails.
 fn git_get_hunks(
     diff: &Diff<'static>,
     vector_of_filenames: Vec<String>,
-) -> Result<Vec<Hunk>, Git2ErrorHandling> {
-    let mut hunk_tuple: Vec<Hunk> = Vec::new();
+) -> Result<Vec<ChangedLine>, Git2ErrorHandling> {
+    let mut hunk_tuple: Vec<ChangedLine> = Vec::new();
     //i returns tuple
     for (int, _delta) in diff.deltas().enumerate() {
         let patch = Patch::from_diff(diff, int)?;
@@ -144,7 +160,7 @@ fn git_get_hunks(
                     ' ' => ChangeType::Modify,
                     _ => continue,
                 };
-                hunk_tuple.push(Hunk {
+                hunk_tuple.push(ChangedLine {
                     change,
                     line: line_processed,
                     filename: vector_of_filenames[int].to_owned(),
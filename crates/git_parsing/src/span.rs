@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::ops::{Range, RangeInclusive};
+
+/// Identifies a file within a run. Ids are handed out by `ParseCache`, `FileId::default()` is used for sources
+/// that were parsed on their own and don't belong to any file.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct FileId(pub u32);
+
+/// A position in a file. `line` is 1-based, `col` is the 1-based byte column within that line.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// A region of a file, carrying both its byte range and its line/column positions, so no stage has to convert
+/// between the two on its own. `bytes` is end-exclusive like `TextRange`, while `start` and `end` are the positions
+/// of its first byte and of its end offset, so `start.line..=end.line` is the 1-based, inclusive range of lines it touches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub file: FileId,
+    pub bytes: Range<usize>,
+    pub start: LineCol,
+    pub end: LineCol,
+}
+
+impl Span {
+    /// Returns the 1-based, inclusive range of lines the span touches.
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.start.line..=self.end.line
+    }
+
+    /// Tells whether the 1-based `line` is one of the lines the span touches.
+    pub fn contains_line(&self, line: usize) -> bool {
+        self.lines().contains(&line)
+    }
+
+    /// Tells whether both spans belong to the same file and share at least one byte, or touch when one of them is empty.
+    /// Two non-empty spans that only touch, such as an item and the line right after it, don't overlap.
+    pub fn overlaps(&self, other: &Span) -> bool {
+        if self.file != other.file {
+            return false;
+        }
+        if self.bytes.is_empty() || other.bytes.is_empty() {
+            self.bytes.start <= other.bytes.end && other.bytes.start <= self.bytes.end
+        } else {
+            self.bytes.start < other.bytes.end && other.bytes.start < self.bytes.end
+        }
+    }
+}
+
+/// Line index of a single source, used to turn byte offsets into `Span`s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    /// Builds the line index of `src`, recording the byte offset at which every line starts.
+    pub fn new(src: &str) -> LineIndex {
+        LineIndex {
+            line_starts: compute_line_starts(src),
+            len: src.len(),
+        }
+    }
+
+    /// Returns the number of lines of the source.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Converts a byte offset into its 1-based line and column.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let line = offset_to_line(offset, &self.line_starts);
+        LineCol {
+            line: line + 1,
+            col: offset - self.line_starts[line] + 1,
+        }
+    }
+
+    /// Converts a byte range of the source, such as a `TextRange` of `rust-analyzer`, into a `Span` of the file `file`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The `FileId` of the file the source was read from.
+    /// * `range` - The byte range to convert.
+    ///
+    /// # Returns
+    ///
+    /// A `Span` covering `range`.
+    pub fn span(&self, file: FileId, range: impl Into<Range<usize>>) -> Span {
+        let bytes = range.into();
+        Span {
+            file,
+            start: self.line_col(bytes.start),
+            end: self.line_col(bytes.end),
+            bytes,
+        }
+    }
+
+    /// Returns the `Span` of the whole 1-based `line`, without its line break. This is how positions that only know
+    /// their line, such as the lines of a patch hunk, are brought into the same representation as parsed items.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The `FileId` of the file the source was read from.
+    /// * `line` - The 1-based line number.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Span>` with the span of the line, or `None` if the source has no such line.
+    pub fn line_span(&self, file: FileId, line: usize) -> Option<Span> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.len, |next| next.saturating_sub(1).max(start));
+        Some(Span {
+            file,
+            bytes: start..end,
+            start: LineCol { line, col: 1 },
+            end: LineCol {
+                line,
+                col: end - start + 1,
+            },
+        })
+    }
+}
+
+/// Computes a vector of byte offsets for the start of each line in a given string slice. This function is a fundamental utility for converting between byte-based `TextRange` (used by syntax parsers) and human-readable line-based ranges.
+/// It iterates through the input string, identifying newline characters to mark the beginning of subsequent lines. This is crucial for accurately mapping parsed syntax tree elements to their corresponding line numbers in a source file.
+///
+/// # Arguments
+///
+/// * `src` - A string slice (`&str`) representing the source code.
+///
+/// # Returns
+///
+/// A `Vec<usize>` where each element is the byte offset of the start of a line.
+fn compute_line_starts(src: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (i, b) in src.bytes().enumerate() {
+        if b == b'\n' {
+            starts.push(i + 1);
+        }
+    }
+    starts
+}
+
+/// Determines the 0-based line number corresponding to a given byte offset within a source text.
+/// This helper function efficiently uses a pre-computed sorted list of line start offsets to find the correct line.
+/// It performs a binary search to locate the line that the specified offset falls into.
+///
+/// # Arguments
+///
+/// * `offset` - The byte offset within the source text for which to find the line number.
+/// * `line_starts` - A slice of `usize` values, where each value is the byte offset of the start of a line.
+///
+/// # Returns
+///
+/// A `usize` representing the 0-based line number corresponding to the given offset.
+fn offset_to_line(offset: usize, line_starts: &[usize]) -> usize {
+    match line_starts.binary_search(&offset) {
+        Ok(line) => line,
+        Err(next_line) => next_line - 1,
+    }
+}
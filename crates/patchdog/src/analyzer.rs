@@ -1,183 +1,1253 @@
 use camino::Utf8Path;
-use ra_ap_base_db::RootQueryDb;
-use ra_ap_hir::{EditionedFileId, Function, ModuleDefId, Semantics};
-use ra_ap_hir_def::{AdtId, FunctionId, db::InternDatabase};
-use ra_ap_project_model::{CargoConfig, ProjectManifest, ProjectWorkspace, RustLibSource};
+use ra_ap_hir::{
+    AssocItem, AssocItemContainer, EditionedFileId, Function, HasSource, HasVisibility, Impl,
+    Module, ModuleDef, PathResolution, Semantics,
+};
+use ra_ap_hir_def::visibility::Visibility as DefVisibility;
+use ra_ap_hir_def::{AdtId, FunctionId, ModuleId, db::InternDatabase};
+use ra_ap_project_model::{
+    CargoConfig, CargoWorkspace, Package, ProjectManifest, ProjectWorkspace, ProjectWorkspaceKind,
+    RustLibSource, Target, TargetKind,
+};
 
-use ra_ap_hir_def::nameres::crate_def_map;
-use ra_ap_ide::{RootDatabase, TextRange};
+use ai_interactions::{PathdogSettings, return_prompt};
+use ra_ap_ide::{Edition, RootDatabase, TextRange};
+use ra_ap_ide_db::defs::Definition;
+use ra_ap_ide_db::documentation::HasDocs;
 use ra_ap_load_cargo::load_workspace;
 use ra_ap_load_cargo::{LoadCargoConfig, ProcMacroServerChoice};
-use ra_ap_syntax::{AstNode, ast};
+use ra_ap_syntax::ast::{HasGenericParams, HasName};
+use ra_ap_syntax::{AstNode, algo, ast};
 use ra_ap_vfs::{AbsPath, Vfs, VfsPath};
+use rust_parsing::ParsedFile;
+use rust_parsing::attributes::is_test_code;
+use rust_parsing::enclosing_type::summary;
+use rust_parsing::error::{
+    AnalyzerInitSnafu, AnalyzerPanicSnafu, AnalyzerTimeoutSnafu, ErrorHandling, UnknownPackageSnafu,
+};
+use rust_parsing::expansion::render_expansion;
+use rust_parsing::skeleton::skeleton;
+use serde_json::{Value, json};
+use snafu::{OptionExt, ResultExt};
 use std::panic::AssertUnwindSafe;
-use std::{collections::HashMap, env, path::Path};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+};
+use tracing::{Level, event};
 
 #[derive(Debug)]
 pub struct AnalyzerData {
     pub db: RootDatabase,
     pub vfs: Vfs,
     pub krates: Vec<ra_ap_base_db::Crate>,
+    pub index: AnalyzerIndex,
 }
+/// Initializes the Rust Analyzer database for the project of `manifest` with the analyzer options of `config.yaml`. Loading always
+/// runs on its own thread, so a panic of rust-analyzer while loading is reported as an error instead of ending the run, and when
+/// `analyzer_timeout_secs` is set, a workspace whose build scripts or proc-macros take too long is given up on instead of
+/// blocking the run. A loader that timed out is told to stop and gives up before its next stage, freeing what it loaded so far;
+/// the stage running at that moment, such as `load_workspace`, can't be interrupted and finishes first.
+///
+/// # Arguments
+/// * `manifest` - The absolute path of the `Cargo.toml` to load.
+/// * `packages` - The workspace members to analyze, every local crate when empty.
+/// * `changed_files` - The files touched by the patch, which decide the crates that are analyzed.
+///
+/// # Returns
+/// A `Result<AnalyzerData, ErrorHandling>` containing the `AnalyzerData` of the loaded workspace.
+///
+/// # Errors
+/// Returns the errors of `load_analyzer`, an `ErrorHandling` if the configuration can't be read, `ErrorHandling::AnalyzerPanic`
+/// if loading panicked, and `ErrorHandling::AnalyzerTimeout` if the workspace didn't load within the timeout.
+pub fn init_analyzer(
+    manifest: PathBuf,
+    packages: Vec<String>,
+    changed_files: Vec<PathBuf>,
+) -> Result<AnalyzerData, ErrorHandling> {
+    let settings = return_prompt()?.patchdog_settings;
+    let seconds = settings.analyzer_timeout_secs;
+    let (sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let loader_cancelled = Arc::clone(&cancelled);
+    thread::spawn(move || {
+        let analyzer_data = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            load_analyzer(
+                &manifest,
+                &packages,
+                &changed_files,
+                &settings,
+                &loader_cancelled,
+            )
+        }))
+        .unwrap_or_else(|payload| {
+            AnalyzerPanicSnafu {
+                message: panic_message(payload.as_ref()),
+            }
+            .fail()
+        });
+        // the receiver is gone once the timeout has passed, the result is dropped then
+        let _ = sender.send(analyzer_data);
+    });
+    let received = if seconds == 0 {
+        receiver
+            .recv()
+            .map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+    } else {
+        receiver.recv_timeout(Duration::from_secs(
+            u64::try_from(seconds).unwrap_or(u64::MAX),
+        ))
+    };
+    match received {
+        Ok(analyzer_data) => analyzer_data,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            cancelled.store(true, Ordering::Relaxed);
+            AnalyzerTimeoutSnafu { seconds }.fail()
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => AnalyzerPanicSnafu {
+            message: "the loader thread stopped without a result",
+        }
+        .fail(),
+    }
+}
+
+/// Extracts the message of a panic payload, which is a `&str` or a `String` for panics raised with a message.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| (*message).to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
 /// Issue related to RPIT panic https://github.com/rust-lang/rust-analyzer/issues/19339
-/// Initializes the Rust Analyzer database and Virtual File System (VFS) for the current project. This function discovers the `Cargo.toml` manifest, loads the workspace, and filters for local crates, returning an `AnalyzerData` struct. This setup is crucial for performing static analysis and code introspection.
+/// Loads the Rust Analyzer database and Virtual File System (VFS) for the project of `manifest`. This function discovers the `Cargo.toml` manifest, loads the workspace, and filters for local crates, keeping only the selected `packages` if any are given, returning an `AnalyzerData` struct.
+/// For Cargo workspaces, only the crates of the packages owning `changed_files` and of the local packages they depend on are kept, so the index, reachability and context lookups never compute the semantics of the rest of the workspace. This setup is crucial for performing static analysis and code introspection.
+/// Unless `load_build_scripts` or `proc_macro_server` needs the Cargo workspace as is, it is then swapped for a `rust-project.json` holding only those crates, see `scoped_project`, so the rest of the workspace is never loaded into the database.
+/// With `dependency_docs`, the dependencies of the workspace are loaded too, offline, so items of other crates can be resolved; they are never indexed.
+/// With `load_build_scripts`, build scripts are run first so code included from `OUT_DIR` is part of the database, and with `proc_macro_server`, derives and attribute macros are expanded by the proc-macro server of the sysroot, so the items they generate can be resolved.
+/// Once the workspace is loaded, the `AnalyzerIndex` of the local crates is built, so later lookups don't have to walk the workspace again.
+///
+/// # Arguments
+/// * `manifest` - The absolute path of the `Cargo.toml` to load.
+/// * `packages` - The workspace members to analyze, every local crate when empty.
+/// * `changed_files` - The files touched by the patch.
+/// * `settings` - The `PathdogSettings` holding the analyzer options.
+/// * `cancelled` - Set once `init_analyzer` stopped waiting, loading then gives up before its next stage.
 ///
 /// # Returns
-/// An `AnalyzerData` struct, containing the initialized `RootDatabase`, `Vfs`, and a vector of `ra_ap_base_db::Crate` instances representing local crates.
-pub fn init_analyzer() -> AnalyzerData {
-    let absolute = env::current_dir().expect("Couldn't get current directory");
-    let cargo_config = CargoConfig {
+/// A `Result<AnalyzerData, ErrorHandling>` containing the initialized `RootDatabase`, `Vfs`, a vector of `ra_ap_base_db::Crate` instances representing local crates and their `AnalyzerIndex`.
+///
+/// # Errors
+/// Returns `ErrorHandling::AnalyzerInit` naming the failed stage if `manifest` can't be found or the workspace can't be loaded,
+/// e.g. because of a missing toolchain or an unreachable registry, and `ErrorHandling::UnknownPackage` if one of `packages`
+/// isn't a local package of the workspace.
+fn load_analyzer(
+    manifest: &Path,
+    packages: &[String],
+    changed_files: &[PathBuf],
+    settings: &PathdogSettings,
+    cancelled: &AtomicBool,
+) -> Result<AnalyzerData, ErrorHandling> {
+    let mut cargo_config = CargoConfig {
         sysroot: Some(RustLibSource::Discover),
         all_targets: true,
-        no_deps: true,
+        //`#[cfg(test)] mod tests;` files are only part of the module tree with cfg(test) set
+        set_test: true,
+        no_deps: !settings.dependency_docs,
         ..Default::default()
     };
-    let as_absolute = absolute.join("Cargo.toml");
-    let binding = as_absolute.display().to_string();
+    //Dependencies are resolved from what cargo already fetched, never from the network
+    if settings.dependency_docs {
+        cargo_config
+            .extra_env
+            .insert("CARGO_NET_OFFLINE".to_string(), Some("true".to_string()));
+    }
+    let binding = manifest.display().to_string();
     let utf8path = AbsPath::assert(Utf8Path::new(&binding));
-    let manifest = ProjectManifest::discover_single(utf8path).expect("Couldn't get manifest");
+    let manifest = ProjectManifest::discover_single(utf8path).context(AnalyzerInitSnafu {
+        stage: "manifest discovery",
+    })?;
     let no_progress = &|prog| println!("{prog}");
-    let workspace = ProjectWorkspace::load(manifest, &cargo_config, no_progress)
-        .expect("Couldn't load Cargo.toml");
+    let mut workspace = ProjectWorkspace::load(manifest, &cargo_config, no_progress).context(
+        AnalyzerInitSnafu {
+            stage: "workspace loading",
+        },
+    )?;
+    check_cancelled(cancelled, settings)?;
+    if settings.load_build_scripts {
+        let build_scripts = workspace
+            .run_build_scripts(&cargo_config, no_progress)
+            .context(AnalyzerInitSnafu {
+                stage: "build scripts",
+            })?;
+        workspace.set_build_scripts(build_scripts);
+    }
+    check_cancelled(cancelled, settings)?;
+    let selected = selected_crates(&workspace, packages)?;
+    let scope = patch_scope(&workspace, changed_files);
+    if !settings.load_build_scripts
+        && !settings.proc_macro_server
+        && let Some(project) = scoped_project(&workspace, changed_files, settings.dependency_docs)
+    {
+        let binding = project.display().to_string();
+        let scoped = ProjectManifest::discover_single(AbsPath::assert(Utf8Path::new(&binding)))
+            .and_then(|manifest| ProjectWorkspace::load(manifest, &cargo_config, no_progress));
+        //The project is read once loaded, the crate graph keeps its own copy
+        if let Some(dir) = project.parent() {
+            let _ = fs::remove_dir_all(dir);
+        }
+        workspace = scoped.context(AnalyzerInitSnafu {
+            stage: "scoped workspace loading",
+        })?;
+        check_cancelled(cancelled, settings)?;
+    }
     let load_cargo_config = LoadCargoConfig {
-        load_out_dirs_from_check: false,
-        with_proc_macro_server: ProcMacroServerChoice::None,
+        load_out_dirs_from_check: settings.load_build_scripts,
+        with_proc_macro_server: if settings.proc_macro_server {
+            ProcMacroServerChoice::Sysroot
+        } else {
+            ProcMacroServerChoice::None
+        },
         prefill_caches: false,
     };
     let (workspace_db, vfs, _proc_macro) =
-        load_workspace(workspace, &cargo_config.extra_env, &load_cargo_config)
-            .expect("Couldn't load project");
+        load_workspace(workspace, &cargo_config.extra_env, &load_cargo_config).context(
+            AnalyzerInitSnafu {
+                stage: "project loading",
+            },
+        )?;
+    check_cancelled(cancelled, settings)?;
 
     let krates = ra_ap_hir::Crate::all(&workspace_db)
         .into_iter()
         .filter_map(|val| {
-            if val.origin(&workspace_db).is_local() {
+            let name = val.display_name(&workspace_db).map(|name| name.to_string());
+            let kept = |crates: &Option<HashSet<String>>| {
+                crates
+                    .as_ref()
+                    .is_none_or(|crates| name.as_ref().is_some_and(|name| crates.contains(name)))
+            };
+            if val.origin(&workspace_db).is_local() && kept(&selected) && kept(&scope) {
                 Some::<ra_ap_base_db::Crate>(val.into())
             } else {
                 None
             }
         })
         .collect::<Vec<ra_ap_base_db::Crate>>();
-    AnalyzerData {
+    let mut analyzer_data = AnalyzerData {
         db: workspace_db,
         vfs,
         krates,
+        index: AnalyzerIndex::default(),
+    };
+    analyzer_data.index = AnalyzerIndex::build(&analyzer_data);
+    Ok(analyzer_data)
+}
+
+/// Gives up loading once `init_analyzer` stopped waiting for it, so a loader that timed out doesn't keep building the
+/// workspace in the background.
+///
+/// # Errors
+/// Returns `ErrorHandling::AnalyzerTimeout` if `cancelled` is set.
+fn check_cancelled(
+    cancelled: &AtomicBool,
+    settings: &PathdogSettings,
+) -> Result<(), ErrorHandling> {
+    if cancelled.load(Ordering::Relaxed) {
+        return AnalyzerTimeoutSnafu {
+            seconds: settings.analyzer_timeout_secs,
+        }
+        .fail();
     }
+    Ok(())
 }
 
-/// Gathers relevant contextual code (functions and structs) from the entire codebase for a specified file and an optional function range. It traverses the `AnalyzerData` to identify local functions and structs, filtering out the target function itself (if `fn_range` is provided) and extracting their source code strings.
+/// Lists the crate names of the targets of the `packages` given with `--package`. Packages are looked up by their name in
+/// the `CargoWorkspace`, so libraries with their own `[lib] name` and the test, bench and example targets of the package
+/// are all selected.
 ///
 /// # Arguments
-/// * `filepath` - The path to the file for which context is being retrieved.
-/// * `fn_range` - An `Option<&TextRange>` specifying a function's range to be excluded from the contextual results.
-/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, and crates.
+/// * `workspace` - The loaded `ProjectWorkspace`.
+/// * `packages` - The package names given with `--package`.
 ///
 /// # Returns
-/// A `HashMap<TextRange, String>` where keys are the text ranges of contextual code elements, and values are their corresponding source code strings.
-pub fn contextualizer(
-    filepath: &Path,
-    fn_range: Option<&TextRange>,
-    analyzer_data: &AnalyzerData,
-) -> HashMap<TextRange, String> {
-    analyzer_data
-        .krates
+/// A `Result<Option<HashSet<String>>, ErrorHandling>` with the names of the selected crates, or `None` when no package is given
+/// or the workspace isn't a Cargo workspace, in which case every local crate is analyzed.
+///
+/// # Errors
+/// Returns `ErrorHandling::UnknownPackage` if a package isn't a local package of the workspace.
+fn selected_crates(
+    workspace: &ProjectWorkspace,
+    packages: &[String],
+) -> Result<Option<HashSet<String>>, ErrorHandling> {
+    if packages.is_empty() {
+        return Ok(None);
+    }
+    let ProjectWorkspaceKind::Cargo { cargo, .. } = &workspace.kind else {
+        event!(
+            Level::WARN,
+            "--package is ignored, the workspace isn't a Cargo workspace"
+        );
+        return Ok(None);
+    };
+    let mut selected = HashSet::new();
+    for name in packages {
+        let package = cargo
+            .packages()
+            .find(|package| cargo[*package].is_local && cargo[*package].name == *name)
+            .context(UnknownPackageSnafu { name })?;
+        // crate names are the target names, with dashes replaced
+        selected.extend(
+            cargo[package]
+                .targets
+                .iter()
+                .map(|target| cargo[*target].name.replace('-', "_")),
+        );
+    }
+    Ok(Some(selected))
+}
+
+/// Lists the crate names of the packages owning `changed_files`, together with the local packages they depend on, directly
+/// or not.
+///
+/// # Arguments
+/// * `workspace` - The loaded `ProjectWorkspace`.
+/// * `changed_files` - The absolute paths of the files touched by the patch.
+///
+/// # Returns
+/// An `Option<HashSet<String>>` with the names of the crates to analyze, or `None` for workspaces that aren't Cargo workspaces,
+/// which are analyzed as a whole.
+fn patch_scope(workspace: &ProjectWorkspace, changed_files: &[PathBuf]) -> Option<HashSet<String>> {
+    let ProjectWorkspaceKind::Cargo { cargo, .. } = &workspace.kind else {
+        return None;
+    };
+    // crate names are the target names, with dashes replaced
+    Some(
+        patch_packages(cargo, changed_files, false)
+            .into_iter()
+            .flat_map(|package| cargo[package].targets.iter())
+            .map(|target| cargo[*target].name.replace('-', "_"))
+            .collect(),
+    )
+}
+
+/// Lists the packages owning `changed_files`, together with the packages they depend on, directly or not. A file belongs
+/// to the package with the deepest manifest directory containing it, so members nested in the directory of the root
+/// package are told apart.
+///
+/// # Arguments
+/// * `cargo` - The `CargoWorkspace` of the loaded workspace.
+/// * `changed_files` - The absolute paths of the files touched by the patch.
+/// * `external` - Whether dependencies outside of the workspace are followed too, or only local packages.
+///
+/// # Returns
+/// A `HashSet<Package>` with the owning packages and their dependencies.
+fn patch_packages(
+    cargo: &CargoWorkspace,
+    changed_files: &[PathBuf],
+    external: bool,
+) -> HashSet<Package> {
+    let mut pending = changed_files
+        .iter()
+        .filter_map(|file| {
+            cargo
+                .packages()
+                .filter(|package| file.starts_with(cargo[*package].manifest.parent().as_std_path()))
+                .max_by_key(|package| cargo[*package].manifest.parent().as_str().len())
+        })
+        .collect::<Vec<Package>>();
+    let mut packages = HashSet::new();
+    while let Some(package) = pending.pop() {
+        if packages.insert(package) {
+            pending.extend(
+                cargo[package]
+                    .dependencies
+                    .iter()
+                    .map(|dependency| dependency.pkg)
+                    .filter(|dependency| external || cargo[*dependency].is_local),
+            );
+        }
+    }
+    packages
+}
+
+/// Writes a `rust-project.json` that describes only the crates of the patch: the targets of the packages owning
+/// `changed_files` and of the local packages they depend on, and with `dependency_docs` the libraries of their other
+/// dependencies. Loading it instead of the Cargo workspace keeps the rest of the workspace out of the crate graph and the
+/// VFS, so load time and memory follow the patch instead of the workspace. Enabled features are kept as cfgs, while build
+/// script outputs and proc-macro dylibs aren't, which is why the Cargo workspace is loaded as is with `load_build_scripts`
+/// or `proc_macro_server`. The sysroot is the one `rustc` reports in the workspace root, so the toolchain stays the same.
+///
+/// # Arguments
+/// * `workspace` - The loaded `ProjectWorkspace`.
+/// * `changed_files` - The absolute paths of the files touched by the patch.
+/// * `dependency_docs` - Whether dependencies outside of the workspace are loaded too.
+///
+/// # Returns
+/// An `Option<PathBuf>` with the path of the written `rust-project.json`, inside a temporary directory of its own, or `None`
+/// if the workspace isn't a Cargo workspace, no package owns a changed file, or the project couldn't be written, in which
+/// case the whole workspace is loaded.
+fn scoped_project(
+    workspace: &ProjectWorkspace,
+    changed_files: &[PathBuf],
+    dependency_docs: bool,
+) -> Option<PathBuf> {
+    let ProjectWorkspaceKind::Cargo { cargo, .. } = &workspace.kind else {
+        return None;
+    };
+    let packages = patch_packages(cargo, changed_files, dependency_docs);
+    if packages.is_empty() {
+        return None;
+    }
+    let is_library = |target: Target| matches!(cargo[target].kind, TargetKind::Lib { .. });
+    //Dependents only link to libraries, so packages outside of the workspace only bring their library along
+    let targets = packages
+        .iter()
+        .flat_map(|package| cargo[*package].targets.iter().copied())
+        .filter(|target| cargo[cargo[*target].package].is_local || is_library(*target))
+        .collect::<Vec<Target>>();
+    let library = |package: Package| {
+        targets
+            .iter()
+            .position(|target| cargo[*target].package == package && is_library(*target))
+    };
+    let crates = targets
         .iter()
-        .flat_map(|krate| {
-            crate_def_map(&analyzer_data.db, *krate)
-                .modules
+        .map(|target| {
+            let data = &cargo[*target];
+            let package = &cargo[data.package];
+            //Binaries, tests and examples link to the library of their own package
+            let own_library = library(data.package)
+                .filter(|_| !is_library(*target))
+                .and_then(|index| {
+                    Some(json!({
+                        "crate": index,
+                        "name": cargo[*targets.get(index)?].name.replace('-', "_"),
+                    }))
+                });
+            let deps = package
+                .dependencies
                 .iter()
-                .flat_map(|(_, module)| {
-                    module.scope.declarations().flat_map(|decl| {
-                        semantic_modules(
-                            decl,
-                            &analyzer_data.db,
-                            fn_range,
-                            &analyzer_data.vfs,
-                            filepath,
-                        )
-                    })
+                .filter_map(|dependency| {
+                    Some(json!({
+                        "crate": library(dependency.pkg)?,
+                        "name": dependency.name.replace('-', "_"),
+                    }))
                 })
+                .chain(own_library)
+                .collect::<Vec<Value>>();
+            json!({
+                "display_name": data.name.replace('-', "_"),
+                "root_module": data.root.as_str(),
+                "edition": package.edition.to_string(),
+                "deps": deps,
+                "cfg": package
+                    .active_features
+                    .iter()
+                    .map(|feature| format!("feature=\"{feature}\""))
+                    .chain(package.is_local.then(|| "test".to_string()))
+                    .collect::<Vec<String>>(),
+                "is_workspace_member": package.is_local,
+                "is_proc_macro": matches!(data.kind, TargetKind::Lib { is_proc_macro: true }),
+                "source": {
+                    "include_dirs": [package.manifest.parent().as_str()],
+                    "exclude_dirs": [],
+                },
+            })
         })
-        .filter(|(range, _)| {
-            // only filter if fn_range was provided
-            fn_range != Some(range)
+        .collect::<Vec<Value>>();
+    let sysroot = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .current_dir(cargo.workspace_root().as_std_path())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())?;
+    let project = json!({
+        "sysroot": sysroot.trim(),
+        "crates": crates,
+    });
+    let dir = env::temp_dir().join(format!("patchdog-{}", uuid::Uuid::new_v4()));
+    let path = dir.join("rust-project.json");
+    fs::create_dir_all(&dir)
+        .and_then(|()| fs::write(&path, project.to_string()))
+        .inspect_err(|err| {
+            event!(
+                Level::WARN,
+                "Couldn't write the scoped project, loading the whole workspace: {err}"
+            )
         })
-        .collect()
+        .ok()?;
+    Some(path)
 }
 
-fn semantic_modules(
-    module: ModuleDefId,
-    db: &RootDatabase,
-    fn_range: Option<&TextRange>,
-    vfs: &Vfs,
-    filepath: &Path,
-) -> HashMap<TextRange, String> {
-    let mut context_strings = HashMap::new();
-    match module {
-        ra_ap_hir_def::ModuleDefId::FunctionId(fn_id) => {
-            let to_path = build_path(fn_id, vfs, db)
-                .cloned();
-
-            if return_functions(db, fn_id) == fn_range.copied()
-                && to_path == Some(VfsPath::new_real_path(filepath.display().to_string()))
-            {
-                let semantics = Semantics::new(db);
-                if let Some(sem) = semantics.source::<ra_ap_hir::Function>(fn_id.into()) {
-                    let fn_node = sem.value;
-
-                    // Insert the function body itself
-                    let range = fn_node.syntax().text_range();
-                    let lookup = &db.lookup_intern_function(fn_id).id;
-                    let file = lookup.file_id.file_id();
-                    if let Some(body) = print_body(file.expect("File ID expected"), range, db) {
-                        context_strings.insert(range, body);
+/// Computes which items of the local crates can be reached from outside of their crate. Starting at each crate root,
+/// the names a module exports with `pub` are followed: public modules are walked in turn, and `pub use` re-exports are
+/// followed while private imports are not, as the visibility checked is the one of the name in the module and not the one
+/// of the item it refers to. Public types bring along their public inherent methods and trait impl methods, public traits
+/// bring along all of their items, and `#[macro_export]` macros, which live in the crate root, are reachable as well.
+///
+/// # Arguments
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, and crates.
+///
+/// # Returns
+/// A `HashSet<(VfsPath, TextRange)>` holding the file and the syntax range of every externally reachable item.
+pub fn externally_reachable(analyzer_data: &AnalyzerData) -> HashSet<(VfsPath, TextRange)> {
+    let db = &analyzer_data.db;
+    let mut reachable = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut modules = analyzer_data
+        .krates
+        .iter()
+        .map(|krate| ra_ap_hir::Crate::from(*krate).root_module())
+        .collect::<Vec<Module>>();
+    while let Some(module) = modules.pop() {
+        if !visited.insert(module) {
+            continue;
+        }
+        for def in exported_names(module, db) {
+            match def {
+                ModuleDef::Module(m) => modules.push(m),
+                ModuleDef::Function(f) => {
+                    reachable.extend(source_location(f, analyzer_data));
+                }
+                ModuleDef::Adt(adt) => {
+                    reachable.extend(source_location(adt, analyzer_data));
+                    for imp in Impl::all_for_type(db, adt.ty(db)) {
+                        let is_trait_impl = imp.trait_(db).is_some();
+                        for item in imp.items(db) {
+                            if let AssocItem::Function(f) = item
+                                && (is_trait_impl || is_public(f.visibility(db)))
+                            {
+                                reachable.extend(source_location(f, analyzer_data));
+                            }
+                        }
+                    }
+                }
+                ModuleDef::Trait(t) => {
+                    reachable.extend(source_location(t, analyzer_data));
+                    for item in t.items(db) {
+                        if let AssocItem::Function(f) = item {
+                            reachable.extend(source_location(f, analyzer_data));
+                        }
                     }
+                }
+                ModuleDef::TypeAlias(t) => {
+                    reachable.extend(source_location(t, analyzer_data));
+                }
+                ModuleDef::Macro(m) => {
+                    reachable.extend(source_location(m, analyzer_data));
+                }
+                _ => {}
+            }
+        }
+    }
+    reachable
+}
 
-                    // Traverse expressions for name resolution
-                    for expr_node in fn_node.syntax().descendants().filter_map(ast::Expr::cast) {
-                        match expr_node {
-                            ast::Expr::CallExpr(call_expr) => {
-                                if let Some(val) = resolve_call_expr(&semantics, &call_expr)
-                                    && let Some(body_range) = function_text_range(val.into(), db)
-                                    && let Some(body) = print_body(body_range.0, body_range.1, db)
-                                {
-                                    context_strings.insert(body_range.1, body);
-                                }
-                            }
-                            ast::Expr::MethodCallExpr(method_expr) => {
-                                if let Some(val) =
-                                    resolve_method_call_expr(&semantics, &method_expr)
-                                    && let Some(body_range) = function_text_range(val.into(), db)
-                                    && let Some(body) =
-                                        print_body(body_range.0, body_range.1, db)
-                                {
-                                    context_strings.insert(body_range.1, body);
-                                }
-                                    
-                            }
-                            _ => {}
+/// Lists the definitions a module exposes under a `pub` name, declared in it or imported with `pub use`, in every namespace.
+/// The entries of the def map's `ItemScope` carry the visibility of the name itself, so a private `use` of a public item
+/// is left out, which `Module::scope` can't tell apart.
+fn exported_names(module: Module, db: &RootDatabase) -> Vec<ModuleDef> {
+    let module_id = ModuleId::from(module);
+    let def_map = module_id.def_map(db);
+    def_map[module_id.local_id]
+        .scope
+        .entries()
+        .flat_map(|(_, per_ns)| {
+            let types = per_ns
+                .types
+                .filter(|item| item.vis == DefVisibility::Public)
+                .map(|item| ModuleDef::from(item.def));
+            let values = per_ns
+                .values
+                .filter(|item| item.vis == DefVisibility::Public)
+                .map(|item| ModuleDef::from(item.def));
+            let macros = per_ns
+                .macros
+                .filter(|item| item.vis == DefVisibility::Public)
+                .map(|item| ModuleDef::Macro(item.def.into()));
+            types.into_iter().chain(values).chain(macros)
+        })
+        .collect()
+}
+
+/// Tells whether a file is only compiled for tests because of the `mod` declaration including it, such as
+/// `#[cfg(test)] mod tests;`, or the declaration of one of its parent modules. The attributes inside the file are already
+/// checked when it is parsed, this covers what only the including file knows.
+///
+/// # Arguments
+/// * `filepath` - The path to the file.
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, and crates.
+///
+/// # Returns
+/// `true` if the module of the file, or one of its parents, is declared with a test attribute.
+pub fn is_test_module(filepath: &Path, analyzer_data: &AnalyzerData) -> bool {
+    let db = &analyzer_data.db;
+    let sema = Semantics::new(db);
+    let path = VfsPath::new_real_path(filepath.display().to_string());
+    let Some((file_id, _)) = analyzer_data.vfs.file_id(&path) else {
+        return false;
+    };
+    let Some(module) = sema.file_to_module_def(file_id) else {
+        return false;
+    };
+    std::iter::successors(Some(module), |module| module.parent(db))
+        .filter_map(|module| module.declaration_source(db))
+        .any(|declaration| is_test_code(declaration.value.syntax()))
+}
+
+fn is_public(visibility: ra_ap_hir::Visibility) -> bool {
+    visibility == ra_ap_hir::Visibility::Public
+}
+
+fn source_location<T>(def: T, analyzer_data: &AnalyzerData) -> Option<(VfsPath, TextRange)>
+where
+    T: HasSource,
+    T::Ast: AstNode,
+{
+    let source = def.source(&analyzer_data.db)?;
+    let file_id = source.file_id.file_id()?;
+    let path = analyzer_data
+        .vfs
+        .file_path(file_id.file_id(&analyzer_data.db))
+        .clone();
+    Some((path, source.value.syntax().text_range()))
+}
+
+/// An item of the local crates the analyzer index points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexedItem {
+    Function(FunctionId),
+    Adt(AdtId),
+}
+
+/// Maps the syntax range of every function and ADT of the local crates, grouped by file, to its `rust-analyzer` id.
+/// Free functions, trait items and the items of every `impl` block are indexed, so any changed function is found with a
+/// direct lookup instead of walking the modules of the whole workspace.
+#[derive(Debug, Default)]
+pub struct AnalyzerIndex {
+    items: HashMap<VfsPath, HashMap<TextRange, IndexedItem>>,
+}
+
+impl AnalyzerIndex {
+    /// Builds the index once for the loaded workspace, walking every module of the local crates.
+    ///
+    /// # Arguments
+    /// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, and crates.
+    ///
+    /// # Returns
+    /// An `AnalyzerIndex` covering the functions and ADTs of all local crates.
+    pub fn build(analyzer_data: &AnalyzerData) -> AnalyzerIndex {
+        let db = &analyzer_data.db;
+        let mut index = AnalyzerIndex::default();
+        for krate in &analyzer_data.krates {
+            for module in ra_ap_hir::Crate::from(*krate).modules(db) {
+                for def in module.declarations(db) {
+                    match def {
+                        ModuleDef::Function(f) => {
+                            index.insert(
+                                source_location(f, analyzer_data),
+                                IndexedItem::Function(f.into()),
+                            );
+                        }
+                        ModuleDef::Adt(adt) => {
+                            index.insert(
+                                source_location(adt, analyzer_data),
+                                IndexedItem::Adt(adt.into()),
+                            );
                         }
+                        ModuleDef::Trait(t) => index.insert_assoc_items(t.items(db), analyzer_data),
+                        _ => {}
                     }
                 }
+                for imp in module.impl_defs(db) {
+                    index.insert_assoc_items(imp.items(db), analyzer_data);
+                }
             }
         }
-        ra_ap_hir_def::ModuleDefId::AdtId(AdtId::StructId(struct_id)) => {
+        index
+    }
+
+    /// Returns the item whose syntax node spans exactly `range` in the file at `path`.
+    pub fn get(&self, path: &VfsPath, range: TextRange) -> Option<IndexedItem> {
+        self.items.get(path)?.get(&range).copied()
+    }
+
+    /// Returns every indexed item of the file at `path`.
+    pub fn items_in_file(&self, path: &VfsPath) -> impl Iterator<Item = IndexedItem> + '_ {
+        self.items
+            .get(path)
+            .into_iter()
+            .flat_map(|items| items.values().copied())
+    }
+
+    fn insert(&mut self, location: Option<(VfsPath, TextRange)>, item: IndexedItem) {
+        if let Some((path, range)) = location {
+            self.items.entry(path).or_default().insert(range, item);
+        }
+    }
+
+    fn insert_assoc_items(&mut self, items: Vec<AssocItem>, analyzer_data: &AnalyzerData) {
+        for item in items {
+            if let AssocItem::Function(f) = item {
+                self.insert(
+                    source_location(f, analyzer_data),
+                    IndexedItem::Function(f.into()),
+                );
+            }
+        }
+    }
+}
+
+/// What a context snippet is to the documented item, which decides how relevant it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnippetKind {
+    /// A function called by the item, or the trait method such a call dispatches to.
+    Callee,
+    /// The definition of a type named in the signature of the item.
+    SignatureType,
+    /// A struct declared in the same file as the item.
+    SameModule,
+    /// Another implementation of the trait method the item implements.
+    SiblingImpl,
+    /// A function calling the item.
+    Caller,
+    /// A test calling the item.
+    Test,
+    /// The signature and docs of an item of a dependency or of the standard library the item refers to.
+    Dependency,
+}
+
+impl SnippetKind {
+    /// Relevance of the kind, snippets with a higher score are kept first when the context budget is exceeded.
+    pub fn score(self) -> u8 {
+        match self {
+            SnippetKind::Callee => 6,
+            SnippetKind::SignatureType => 5,
+            SnippetKind::SameModule => 4,
+            SnippetKind::SiblingImpl => 3,
+            SnippetKind::Caller => 2,
+            SnippetKind::Test => 1,
+            SnippetKind::Dependency => 0,
+        }
+    }
+}
+
+/// A piece of source code gathered as context for a documented item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextSnippet {
+    pub kind: SnippetKind,
+    pub range: TextRange,
+    pub text: String,
+}
+
+impl ContextSnippet {
+    /// Reduces the snippet to its skeleton with `rust_parsing::skeleton::skeleton`, keeping signatures, docs and fields
+    /// but eliding function bodies. Callees no longer than `full_body_threshold` keep their body, as do sibling impls,
+    /// callers and tests, whose bodies are what makes them useful.
+    ///
+    /// # Arguments
+    /// * `full_body_threshold` - The maximum length, in characters, of a callee that is kept in full.
+    /// * `edition` - The edition the snippet is parsed with.
+    ///
+    /// # Returns
+    /// The `ContextSnippet` with its text replaced by the skeleton, or unchanged if its body is kept.
+    pub fn skeletonize(self, full_body_threshold: usize, edition: Edition) -> ContextSnippet {
+        let keep_body = match self.kind {
+            SnippetKind::Callee => self.text.len() <= full_body_threshold,
+            SnippetKind::SignatureType | SnippetKind::SameModule | SnippetKind::Dependency => false,
+            SnippetKind::SiblingImpl | SnippetKind::Caller | SnippetKind::Test => true,
+        };
+        if keep_body {
+            return self;
+        }
+        ContextSnippet {
+            text: skeleton(&self.text, edition),
+            ..self
+        }
+    }
+}
+
+/// Orders context snippets by relevance and keeps as many of them as fit in `budget`. Snippets are sorted by the score of
+/// their kind, then by length and text, so the result doesn't depend on the order they were gathered in. Duplicates are
+/// dropped, and snippets are taken in that order until one doesn't fit in what is left of the budget: it is dropped
+/// together with every snippet after it, so a lower-value snippet is never kept in place of a higher-value one.
+///
+/// # Arguments
+/// * `snippets` - The gathered `ContextSnippet`s, in any order.
+/// * `budget` - The maximum combined length of the kept snippets, in characters.
+///
+/// # Returns
+/// A `Vec<ContextSnippet>` with the kept snippets, most relevant first.
+pub fn rank_snippets(mut snippets: Vec<ContextSnippet>, budget: usize) -> Vec<ContextSnippet> {
+    snippets.sort_by(|a, b| {
+        b.kind
+            .score()
+            .cmp(&a.kind.score())
+            .then_with(|| a.text.len().cmp(&b.text.len()))
+            .then_with(|| a.text.cmp(&b.text))
+    });
+    let mut seen = HashSet::new();
+    let mut remaining = budget;
+    snippets
+        .into_iter()
+        .filter(|snippet| seen.insert(snippet.text.clone()))
+        .take_while(|snippet| {
+            let fits = snippet.text.len() <= remaining;
+            if fits {
+                remaining -= snippet.text.len();
+            }
+            fits
+        })
+        .collect()
+}
+
+/// Gathers relevant contextual code (functions and structs) for a specified file and an optional function range. The function is looked up in the `AnalyzerIndex`, the functions it calls, the definitions of the types named in its signature and other implementations of the trait method it implements are collected, together with the structs declared in the same file, filtering out the target function itself (if `fn_range` is provided).
+///
+/// # Arguments
+/// * `filepath` - The path to the file for which context is being retrieved.
+/// * `fn_range` - An `Option<&TextRange>` specifying a function's range to be excluded from the contextual results.
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, crates and index.
+///
+/// # Returns
+/// A `Vec<ContextSnippet>` with the source code of every contextual code element, tagged with its `SnippetKind`, to be ordered and trimmed with `rank_snippets`.
+pub fn contextualizer(
+    filepath: &Path,
+    fn_range: Option<&TextRange>,
+    analyzer_data: &AnalyzerData,
+) -> Vec<ContextSnippet> {
+    let db = &analyzer_data.db;
+    let path = VfsPath::new_real_path(filepath.display().to_string());
+    let mut snippets = vec![];
+    if let Some(range) = fn_range
+        && let Some(IndexedItem::Function(fn_id)) = analyzer_data.index.get(&path, *range)
+    {
+        snippets.extend(function_context(fn_id, db));
+    }
+    for item in analyzer_data.index.items_in_file(&path) {
+        if let IndexedItem::Adt(AdtId::StructId(struct_id)) = item {
             let item_struct = db.lookup_intern_struct(struct_id);
             if let Some(file_id) = item_struct.id.file_id.file_id() {
-                let actual = vfs.file_path(file_id.file_id(db));
-                if *actual
-                    == VfsPath::new_real_path(filepath.to_str().unwrap_or_default().to_owned())
+                let range = item_struct.id.to_ptr(db).syntax_node_ptr().text_range();
+                if let Some(text) = print_body(file_id, range, db) {
+                    snippets.push(ContextSnippet {
+                        kind: SnippetKind::SameModule,
+                        range,
+                        text,
+                    });
+                }
+            }
+        }
+    }
+    snippets
+        .into_iter()
+        .filter(|snippet| {
+            // only filter if fn_range was provided
+            fn_range != Some(&snippet.range)
+        })
+        .collect()
+}
+
+/// Gathers context without `rust-analyzer`, for runs where the workspace couldn't be loaded. Only the items of the same
+/// file, as parsed by `RustItemParser`, are available: its structs, enums, traits and type aliases, and the functions of
+/// the file the documented function calls. Calls are read from the call and method call expressions of its body: a plain
+/// or module qualified call matches a free function, `Type::name()` a function of an impl of `Type` (`Self` being the type
+/// of the documented method), and `x.name()` a method taking `self`. So `Vec::new()` never matches a same-file `fn new`
+/// of another type, and `my_helper()` never matches `fn helper`.
+///
+/// # Arguments
+/// * `parsed_file` - The `ParsedFile` containing the documented function.
+/// * `fn_range` - The `TextRange` of the documented function.
+///
+/// # Returns
+/// A `Vec<ContextSnippet>` with the same-file items, as `SameModule` snippets and called functions as `Callee` snippets.
+pub fn syntax_context(parsed_file: &ParsedFile, fn_range: TextRange) -> Vec<ContextSnippet> {
+    let functions = parsed_file
+        .parse
+        .tree()
+        .syntax()
+        .descendants()
+        .filter_map(ast::Fn::cast)
+        .map(|function| (function.syntax().text_range(), function))
+        .collect::<HashMap<TextRange, ast::Fn>>();
+    let Some(documented) = functions.get(&fn_range) else {
+        return vec![];
+    };
+    let calls = syntax_calls(documented);
+    let own_type = container_name(documented);
+    parsed_file
+        .items
+        .values()
+        .filter(|item| !item.range.contains_range(fn_range))
+        .filter_map(|item| {
+            let kind = match item.names.type_name.as_str() {
+                "struct" | "enum" | "trait" | "type_alias" => SnippetKind::SameModule,
+                "fn" if functions.get(&item.range).is_some_and(|candidate| {
+                    calls
+                        .iter()
+                        .any(|call| call.matches(candidate, own_type.as_deref()))
+                }) =>
                 {
-                    let range = item_struct.id.to_ptr(db).syntax_node_ptr().text_range();
-                    if let Some(body) = print_body(file_id, range, db) {
-                        context_strings.insert(range, body);
-                    }
+                    SnippetKind::Callee
+                }
+                _ => return None,
+            };
+            let text = parsed_file
+                .source
+                .get(std::ops::Range::<usize>::from(item.range))?;
+            Some(ContextSnippet {
+                kind,
+                range: item.range,
+                text: text.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// A call found in the syntax of a function body, before any name resolution.
+enum SyntaxCall {
+    /// `name()`, or `module::name()`.
+    Free(String),
+    /// `Type::name()`, holding the last segment of the type path and the name.
+    Associated(String, String),
+    /// `receiver.name()`.
+    Method(String),
+}
+
+impl SyntaxCall {
+    /// Tells whether `candidate` may be the function called, `own_type` being the type `Self` stands for.
+    fn matches(&self, candidate: &ast::Fn, own_type: Option<&str>) -> bool {
+        let Some(name) = candidate.name().map(|name| name.to_string()) else {
+            return false;
+        };
+        let container = container_name(candidate);
+        match self {
+            SyntaxCall::Free(called) => container.is_none() && *called == name,
+            SyntaxCall::Associated(qualifier, called) => {
+                let qualifier = if qualifier == "Self" {
+                    own_type
+                } else {
+                    Some(qualifier.as_str())
+                };
+                *called == name && container.is_some() && container.as_deref() == qualifier
+            }
+            SyntaxCall::Method(called) => {
+                *called == name
+                    && candidate
+                        .param_list()
+                        .and_then(|params| params.self_param())
+                        .is_some()
+            }
+        }
+    }
+}
+
+/// Collects the calls of a function body from its call and method call expressions.
+fn syntax_calls(function: &ast::Fn) -> Vec<SyntaxCall> {
+    function
+        .syntax()
+        .descendants()
+        .filter_map(|node| {
+            if let Some(method) = ast::MethodCallExpr::cast(node.clone()) {
+                return Some(SyntaxCall::Method(method.name_ref()?.to_string()));
+            }
+            let ast::Expr::PathExpr(callee) = ast::CallExpr::cast(node)?.expr()? else {
+                return None;
+            };
+            let path = callee.path()?;
+            let name = path.segment()?.name_ref()?.to_string();
+            let qualifier = path
+                .qualifier()
+                .and_then(|qualifier| qualifier.segment())
+                .map(|segment| {
+                    segment
+                        .name_ref()
+                        .map_or_else(|| segment.syntax().text().to_string(), |n| n.to_string())
+                });
+            // types are written in UpperCamelCase, modules and `self`, `super` or `crate` are not
+            Some(match qualifier {
+                Some(qualifier) if qualifier.starts_with(char::is_uppercase) => {
+                    SyntaxCall::Associated(qualifier, name)
+                }
+                _ => SyntaxCall::Free(name),
+            })
+        })
+        .collect()
+}
+
+/// Returns the name of the type or trait whose `impl` or `trait` block declares `function`, `Foo` for `impl<T> Foo<T>`.
+fn container_name(function: &ast::Fn) -> Option<String> {
+    let container = function.syntax().parent()?.parent()?;
+    if let Some(impl_) = ast::Impl::cast(container.clone()) {
+        let ast::Type::PathType(self_ty) = impl_.self_ty()? else {
+            return None;
+        };
+        return self_ty
+            .path()?
+            .segment()?
+            .name_ref()
+            .map(|name| name.to_string());
+    }
+    ast::Trait::cast(container)?
+        .name()
+        .map(|name| name.to_string())
+}
+
+fn function_context(fn_id: FunctionId, db: &RootDatabase) -> Vec<ContextSnippet> {
+    let mut snippets = vec![];
+    let semantics = Semantics::new(db);
+    if let Some(sem) = semantics.source::<ra_ap_hir::Function>(fn_id.into()) {
+        let fn_node = sem.value;
+
+        // Traverse expressions for name resolution
+        for expr_node in fn_node.syntax().descendants().filter_map(ast::Expr::cast) {
+            let callee = match expr_node {
+                ast::Expr::CallExpr(call_expr) => resolve_call_expr(&semantics, &call_expr),
+                ast::Expr::MethodCallExpr(method_expr) => {
+                    resolve_method_call_expr(&semantics, &method_expr)
+                }
+                _ => None,
+            };
+            //Functions of other crates are only described by `dependency_context`, when enabled
+            for function in callee
+                .into_iter()
+                .flat_map(|f| with_trait_declaration(f, db))
+                .filter(|f| f.module(db).krate().origin(db).is_local())
+            {
+                if let Some((file_id, range)) = function_text_range(function.into(), db)
+                    && let Some(text) = print_body(file_id, range, db)
+                {
+                    snippets.push(ContextSnippet {
+                        kind: SnippetKind::Callee,
+                        range,
+                        text,
+                    });
                 }
             }
         }
-        _ => {}
+
+        snippets.extend(signature_types(&semantics, &fn_node, db));
+        snippets.extend(sibling_implementations(fn_id.into(), db));
     }
-    context_strings
+    snippets
+}
+
+/// Upper bound on the number of calling snippets collected for a single function, callers in other modules come first.
+const MAX_FUNCTION_USAGES: usize = 3;
+
+/// Upper bound on the number of tests collected as examples for a single function, the shortest ones come first.
+const MAX_TEST_EXAMPLES: usize = 3;
+
+/// Finds the functions calling a changed function across the workspace, so the LLM sees how the function is used and not
+/// only what it calls. References are found with `rust-analyzer`'s reference search, and each distinct calling function
+/// is returned once. Tests referencing the function are kept apart, as they are the best source for its `# Examples`.
+/// Callers in other modules than the function itself are preferred, as they show its intended use from the outside;
+/// recursive calls are skipped.
+///
+/// # Arguments
+/// * `filepath` - The path to the file containing the function.
+/// * `fn_range` - The `TextRange` of the function item.
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, crates and index.
+///
+/// # Returns
+/// A `Vec<ContextSnippet>` with up to `MAX_FUNCTION_USAGES` calling functions of kind `Caller` and up to `MAX_TEST_EXAMPLES`
+/// tests of kind `Test`, each in a stable order.
+pub fn function_usages(
+    filepath: &Path,
+    fn_range: TextRange,
+    analyzer_data: &AnalyzerData,
+) -> Vec<ContextSnippet> {
+    let db = &analyzer_data.db;
+    let path = VfsPath::new_real_path(filepath.display().to_string());
+    let Some(IndexedItem::Function(fn_id)) = analyzer_data.index.get(&path, fn_range) else {
+        return vec![];
+    };
+    let function = Function::from(fn_id);
+    let module = function.module(db);
+    let sema = Semantics::new(db);
+    let catch_panic = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        Definition::Function(function).usages(&sema).all()
+    }));
+    let Ok(usages) = catch_panic else {
+        return vec![];
+    };
+    let mut callers = HashMap::new();
+    let mut tests = HashMap::new();
+    for (usage_file, references) in usages {
+        let root = sema.parse(usage_file);
+        for reference in references {
+            let Some(caller) =
+                algo::find_node_at_offset::<ast::Fn>(root.syntax(), reference.range.start())
+            else {
+                continue;
+            };
+            let Some(caller_def) = sema.to_def(&caller) else {
+                continue;
+            };
+            if caller_def == function {
+                continue;
+            }
+            let text = caller.syntax().text().to_string();
+            let range = caller.syntax().text_range();
+            if caller_def.is_test(db) {
+                tests.insert(text, range);
+            } else {
+                let same_module = caller_def.module(db) == module;
+                callers.entry(text).or_insert((same_module, range));
+            }
+        }
+    }
+    let mut callers = callers
+        .into_iter()
+        .collect::<Vec<(String, (bool, TextRange))>>();
+    callers.sort_by(|(text_a, (same_a, _)), (text_b, (same_b, _))| {
+        same_a.cmp(same_b).then_with(|| text_a.cmp(text_b))
+    });
+    let mut tests = tests.into_iter().collect::<Vec<(String, TextRange)>>();
+    tests.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    let callers = callers
+        .into_iter()
+        .take(MAX_FUNCTION_USAGES)
+        .map(|(text, (_, range))| ContextSnippet {
+            kind: SnippetKind::Caller,
+            range,
+            text,
+        });
+    let tests = tests
+        .into_iter()
+        .take(MAX_TEST_EXAMPLES)
+        .map(|(text, range)| ContextSnippet {
+            kind: SnippetKind::Test,
+            range,
+            text,
+        });
+    callers.chain(tests).collect()
+}
+
+/// Upper bound on the number of invocations collected for a single macro, a few are enough as usage examples.
+const MAX_MACRO_USAGES: usize = 5;
+
+/// Finds invocations of a `macro_rules!` definition across the workspace, to be used as usage examples in its documentation.
+/// The definition is located by its file and syntax range, resolved to a `hir::Macro` and searched for with `rust-analyzer`'s
+/// reference search. Invocations inside the definition itself, such as recursive calls, are skipped.
+///
+/// # Arguments
+/// * `filepath` - The path to the file containing the macro definition.
+/// * `macro_range` - The `TextRange` of the `macro_rules!` item.
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, and crates.
+///
+/// # Returns
+/// A `Vec<String>` with the source text of up to `MAX_MACRO_USAGES` distinct invocations, sorted for a stable output.
+pub fn macro_usages(
+    filepath: &Path,
+    macro_range: TextRange,
+    analyzer_data: &AnalyzerData,
+) -> Vec<String> {
+    let db = &analyzer_data.db;
+    let sema = Semantics::new(db);
+    let path = VfsPath::new_real_path(filepath.display().to_string());
+    let Some((file_id, _)) = analyzer_data.vfs.file_id(&path) else {
+        return vec![];
+    };
+    let Some(definition) = sema
+        .parse_guess_edition(file_id)
+        .syntax()
+        .descendants()
+        .filter_map(ast::Macro::cast)
+        .find(|m| m.syntax().text_range() == macro_range)
+        .and_then(|m| sema.to_def(&m))
+    else {
+        return vec![];
+    };
+    let catch_panic = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        Definition::Macro(definition).usages(&sema).all()
+    }));
+    let Ok(usages) = catch_panic else {
+        return vec![];
+    };
+    let mut invocations = usages
+        .into_iter()
+        .flat_map(|(usage_file, references)| {
+            let root = db.parse(usage_file).syntax_node();
+            let in_definition_file = usage_file.file_id(db) == file_id;
+            references.into_iter().filter_map(move |reference| {
+                if in_definition_file && macro_range.contains_range(reference.range) {
+                    return None;
+                }
+                algo::find_node_at_range::<ast::MacroCall>(&root, reference.range)
+                    .map(|call| call.syntax().text().to_string())
+            })
+        })
+        .collect::<Vec<String>>();
+    invocations.sort();
+    invocations.dedup();
+    invocations.truncate(MAX_MACRO_USAGES);
+    invocations
+}
+
+/// Expands the macro calls of a changed item, so the LLM sees the code behind `event!`, context selectors or local
+/// `macro_rules!` instead of opaque invocations. The calls are the ones in the item, such as those in a function body,
+/// or the item itself when it is an item-level macro call. Each expansion is rendered with `render_expansion` under a
+/// `// <macro>! expands to` line, and expansions are kept in the order of the calls for as long as they fit in `budget`.
+///
+/// # Arguments
+/// * `filepath` - The path to the file containing the item.
+/// * `item_range` - The `TextRange` of the item.
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, and crates.
+/// * `budget` - The maximum combined length of the returned expansions, in characters.
+///
+/// # Returns
+/// A `Vec<String>` with the distinct expansions, empty if the item contains no macro call that could be expanded.
+pub fn macro_expansions(
+    filepath: &Path,
+    item_range: TextRange,
+    analyzer_data: &AnalyzerData,
+    budget: usize,
+) -> Vec<String> {
+    let sema = Semantics::new(&analyzer_data.db);
+    let path = VfsPath::new_real_path(filepath.display().to_string());
+    let Some((file_id, _)) = analyzer_data.vfs.file_id(&path) else {
+        return vec![];
+    };
+    let Some(item) = sema
+        .parse_guess_edition(file_id)
+        .syntax()
+        .descendants()
+        .find(|node| {
+            node.text_range() == item_range
+                && (ast::Item::can_cast(node.kind()) || ast::AssocItem::can_cast(node.kind()))
+        })
+    else {
+        return vec![];
+    };
+    let mut remaining = budget;
+    let mut expansions: Vec<String> = vec![];
+    //Calls nested in the arguments of another call are token trees, they are only seen through the outer expansion
+    for call in item.descendants().filter_map(ast::MacroCall::cast) {
+        let catch_panic =
+            std::panic::catch_unwind(AssertUnwindSafe(|| sema.expand_macro_call(&call)));
+        let Some(expansion) = catch_panic.ok().flatten() else {
+            continue;
+        };
+        let name = call.path().map(|path| path.to_string()).unwrap_or_default();
+        let text = format!(
+            "// {name}! expands to\n{}",
+            render_expansion(&expansion.value)
+        );
+        if text.len() <= remaining && !expansions.contains(&text) {
+            remaining -= text.len();
+            expansions.push(text);
+        }
+    }
+    expansions
 }
 
 // Helper: get the TextRange and file_id of a FunctionId without calling infer
@@ -192,6 +1262,16 @@ fn function_text_range(
     Some((file_id, range))
 }
 
+/// Resolves the function called by a call expression whose callee is a path, such as `foo()`, `Self::new()` or
+/// `Type::associated()`. For trait methods called on a known type, `rust-analyzer` resolves the path to the method of the
+/// concrete impl, otherwise to the trait declaration.
+///
+/// # Arguments
+/// * `sema` - The `Semantics` the enclosing function was loaded through.
+/// * `call` - The call expression to resolve.
+///
+/// # Returns
+/// An `Option<Function>` with the called function, or `None` if the callee is not a path to a function or resolution panics.
 fn resolve_call_expr(sema: &Semantics<'_, RootDatabase>, call: &ast::CallExpr) -> Option<Function> {
     if let Some(path_expr) = call
         .expr()
@@ -208,43 +1288,291 @@ fn resolve_call_expr(sema: &Semantics<'_, RootDatabase>, call: &ast::CallExpr) -
     None
 }
 
+/// Resolves the method called by a method call expression, `bar` in `foo.bar()`, using the type of the receiver.
+/// Trait methods resolve to the method of the concrete impl when the receiver type determines it, and to the
+/// trait declaration otherwise.
+///
+/// # Arguments
+/// * `sema` - The `Semantics` the enclosing function was loaded through.
+/// * `call` - The method call expression to resolve.
+///
+/// # Returns
+/// An `Option<Function>` with the called method, or `None` if it can't be resolved or resolution panics.
 fn resolve_method_call_expr(
     sema: &Semantics<'_, RootDatabase>,
     call: &ast::MethodCallExpr,
 ) -> Option<Function> {
-    // The thing being called (`foo` in `foo()`)
-    if let Some(path_expr) = call
-        .receiver()
-        .and_then(|e| ast::PathExpr::cast(e.syntax().clone()))
-        && let Some(path) = path_expr.path()
+    let catch_panic = std::panic::catch_unwind(AssertUnwindSafe(|| sema.resolve_method_call(call)));
+    catch_panic.ok()?
+}
+
+/// Returns the function together with the trait method it implements, when it is an item of a trait impl.
+/// A call resolved to an impl is then documented with both the concrete body and the contract of the trait.
+fn with_trait_declaration(function: Function, db: &RootDatabase) -> Vec<Function> {
+    let mut functions = vec![function];
+    if let Some(trait_) = function
+        .as_assoc_item(db)
+        .and_then(|item| item.implemented_trait(db))
+        && let Some(declaration) = trait_.items(db).into_iter().find_map(|item| match item {
+            AssocItem::Function(f) if f.name(db) == function.name(db) => Some(f),
+            _ => None,
+        })
+    {
+        functions.push(declaration);
+    }
+    functions
+}
+
+/// Resolves every type named in the signature of a function: its parameters, return type, generic bounds and
+/// where-clause predicates, including the types nested in generic arguments, such as `ResponseForm` and `ErrorBinding`
+/// in `Result<Vec<ResponseForm>, ErrorBinding>`. Only definitions from the local crates are kept.
+///
+/// # Arguments
+/// * `sema` - The `Semantics` the function was loaded through.
+/// * `fn_node` - The `ast::Fn` node of the function.
+/// * `db` - The `RootDatabase` of the workspace.
+///
+/// # Returns
+/// A `Vec<ContextSnippet>` with the text of every struct, enum, union, trait or type alias definition found.
+fn signature_types(
+    sema: &Semantics<'_, RootDatabase>,
+    fn_node: &ast::Fn,
+    db: &RootDatabase,
+) -> Vec<ContextSnippet> {
+    let signature = [
+        fn_node
+            .generic_param_list()
+            .map(|list| list.syntax().clone()),
+        fn_node.param_list().map(|list| list.syntax().clone()),
+        fn_node.ret_type().map(|ret| ret.syntax().clone()),
+        fn_node.where_clause().map(|clause| clause.syntax().clone()),
+    ];
+    let mut definitions = vec![];
+    for path in signature
+        .into_iter()
+        .flatten()
+        .flat_map(|node| node.descendants().filter_map(ast::PathType::cast))
+        .filter_map(|path_type| path_type.path())
     {
         let catch_panic = std::panic::catch_unwind(AssertUnwindSafe(|| sema.resolve_path(&path)));
-        if let Some(ra_ap_hir::PathResolution::Def(ra_ap_hir::ModuleDef::Function(f))) =
-            catch_panic.ok()?
-        {
-            return Some(f);
+        let definition = match catch_panic.ok().flatten() {
+            Some(PathResolution::Def(ModuleDef::Adt(adt))) => {
+                local_definition(adt, adt.module(db), db)
+            }
+            Some(PathResolution::Def(ModuleDef::Trait(trait_))) => {
+                local_definition(trait_, trait_.module(db), db)
+            }
+            Some(PathResolution::Def(ModuleDef::TypeAlias(alias))) => {
+                local_definition(alias, alias.module(db), db)
+            }
+            Some(PathResolution::SelfType(impl_)) => impl_
+                .self_ty(db)
+                .as_adt()
+                .and_then(|adt| local_definition(adt, adt.module(db), db)),
+            _ => None,
+        };
+        if let Some((range, text)) = definition {
+            definitions.push(ContextSnippet {
+                kind: SnippetKind::SignatureType,
+                range,
+                text,
+            });
         }
     }
-    None
+    definitions
+}
+
+/// Returns the range and the text, doc comments included, of a definition that belongs to one of the local crates.
+fn local_definition<T>(def: T, module: Module, db: &RootDatabase) -> Option<(TextRange, String)>
+where
+    T: HasSource,
+    T::Ast: AstNode,
+{
+    if !module.krate().origin(db).is_local() {
+        return None;
+    }
+    definition_text(def, db)
 }
 
-/// Retrieves the Virtual File System (VFS) path associated with a given `FunctionId`. It queries the `RootDatabase` to look up the function's definition and then uses the extracted `FileId` to obtain the corresponding `VfsPath` from the `Vfs`.
+/// Returns the range and the text, doc comments included, of a definition written in a source file, whichever crate it belongs to.
+fn definition_text<T>(def: T, db: &RootDatabase) -> Option<(TextRange, String)>
+where
+    T: HasSource,
+    T::Ast: AstNode,
+{
+    let source = def.source(db)?;
+    let file_id = source.file_id.file_id()?;
+    let range = source.value.syntax().text_range();
+    Some((range, print_body(file_id, range, db)?))
+}
+
+/// Describes the items of dependencies and of the standard library a changed function refers to, so the LLM learns what
+/// the APIs it wraps do: the functions and methods it calls, and the types, traits and aliases it names. Their sources are
+/// read from the database, which holds the registry sources, vendored sources and sysroot the workspace was loaded with,
+/// so nothing is fetched. Each item is reduced to its doc comment and signature with `skeleton`, headed by the name of its
+/// crate, and items are kept in the order they are referenced for as long as they fit in `budget`.
 ///
 /// # Arguments
-/// * `fn_id` - The `FunctionId` for which to find the file path.
-/// * `vfs` - A reference to the `Vfs` (Virtual File System).
-/// * `db` - A reference to the `RootDatabase` (Rust Analyzer database).
+/// * `filepath` - The path to the file containing the function.
+/// * `fn_range` - The `TextRange` of the function item.
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, crates and index.
+/// * `budget` - The maximum combined length of the returned snippets, in characters.
 ///
 /// # Returns
-/// An `Option<&'a VfsPath>` containing a reference to the `VfsPath` if the function's file ID can be successfully resolved, otherwise `None`.
-fn build_path<'a>(fn_id: FunctionId, vfs: &'a Vfs, db: &RootDatabase) -> Option<&'a VfsPath> {
-    let lookup = &db.lookup_intern_function(fn_id);
-    if let Some(file_id) = lookup.id.file_id.file_id() {
-        let actual = vfs.file_path(file_id.file_id(db));
-        Some(actual)
-    } else {
-        None
+/// A `Vec<ContextSnippet>` of kind `SnippetKind::Dependency`, empty if the function isn't indexed or only refers to local items.
+pub fn dependency_context(
+    filepath: &Path,
+    fn_range: TextRange,
+    analyzer_data: &AnalyzerData,
+    budget: usize,
+) -> Vec<ContextSnippet> {
+    let db = &analyzer_data.db;
+    let path = VfsPath::new_real_path(filepath.display().to_string());
+    let Some(IndexedItem::Function(fn_id)) = analyzer_data.index.get(&path, fn_range) else {
+        return vec![];
+    };
+    let semantics = Semantics::new(db);
+    let Some(fn_node) = semantics.source::<Function>(fn_id.into()) else {
+        return vec![];
+    };
+    let mut seen = HashSet::new();
+    let mut remaining = budget;
+    let mut snippets = vec![];
+    for node in fn_node.value.syntax().descendants() {
+        let definition = if let Some(method) = ast::MethodCallExpr::cast(node.clone()) {
+            resolve_method_call_expr(&semantics, &method).map(ModuleDef::Function)
+        } else if let Some(path) = ast::Path::cast(node)
+            // only whole paths, `Diff` in `git2::Diff` is resolved through its parent
+            && path.parent_path().is_none()
+        {
+            let catch_panic =
+                std::panic::catch_unwind(AssertUnwindSafe(|| semantics.resolve_path(&path)));
+            match catch_panic.ok().flatten() {
+                Some(PathResolution::Def(definition)) => Some(definition),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        if let Some(definition) = definition
+            && seen.insert(definition)
+            && let Some(snippet) = dependency_snippet(definition, db)
+            && snippet.text.len() <= remaining
+        {
+            remaining -= snippet.text.len();
+            snippets.push(snippet);
+        }
     }
+    snippets
+}
+
+/// Renders a function, type, trait or type alias of a non-local crate as its skeleton, headed by a `// crate <name>` line.
+fn dependency_snippet(definition: ModuleDef, db: &RootDatabase) -> Option<ContextSnippet> {
+    let krate = definition.module(db)?.krate();
+    if krate.origin(db).is_local() {
+        return None;
+    }
+    let (range, text) = match definition {
+        ModuleDef::Function(function) => definition_text(function, db),
+        ModuleDef::Adt(adt) => definition_text(adt, db),
+        ModuleDef::Trait(trait_) => definition_text(trait_, db),
+        ModuleDef::TypeAlias(alias) => definition_text(alias, db),
+        _ => None,
+    }?;
+    let name = krate
+        .display_name(db)
+        .map(|name| name.to_string())
+        .unwrap_or_default();
+    Some(ContextSnippet {
+        kind: SnippetKind::Dependency,
+        range,
+        text: format!("// crate {name}\n{}", skeleton(&text, krate.edition(db))),
+    })
+}
+
+/// Upper bound on the number of other implementations of the same trait method added to the context.
+const MAX_SIBLING_IMPLS: usize = 2;
+
+/// Returns the declaration of the trait method a function implements, doc comments included, so the generated
+/// documentation can build on the contract of the trait instead of restating it.
+///
+/// # Arguments
+/// * `filepath` - The path to the file containing the function.
+/// * `fn_range` - The `TextRange` of the function item.
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, crates and index.
+///
+/// # Returns
+/// An `Option<String>` with the source text of the trait method, or `None` if the function is not part of a trait impl.
+pub fn trait_declaration(
+    filepath: &Path,
+    fn_range: TextRange,
+    analyzer_data: &AnalyzerData,
+) -> Option<String> {
+    let db = &analyzer_data.db;
+    let path = VfsPath::new_real_path(filepath.display().to_string());
+    let Some(IndexedItem::Function(fn_id)) = analyzer_data.index.get(&path, fn_range) else {
+        return None;
+    };
+    let declaration = with_trait_declaration(fn_id.into(), db)
+        .into_iter()
+        .nth(1)?;
+    let (file_id, range) = function_text_range(declaration.into(), db)?;
+    print_body(file_id, range, db)
+}
+
+/// Returns the summary of the documentation of the type or trait a method belongs to, wherever it is declared: the self type
+/// of its impl when it is an ADT, or its trait for trait items.
+///
+/// # Arguments
+/// * `filepath` - The path to the file containing the method.
+/// * `fn_range` - The `TextRange` of the method.
+/// * `analyzer_data` - A reference to the `AnalyzerData` containing the Rust Analyzer database, VFS, crates and index.
+///
+/// # Returns
+/// An `Option<String>` with the first paragraph of the documentation, or `None` if the function isn't a method or the type is undocumented.
+pub fn type_doc(
+    filepath: &Path,
+    fn_range: TextRange,
+    analyzer_data: &AnalyzerData,
+) -> Option<String> {
+    let db = &analyzer_data.db;
+    let path = VfsPath::new_real_path(filepath.display().to_string());
+    let Some(IndexedItem::Function(fn_id)) = analyzer_data.index.get(&path, fn_range) else {
+        return None;
+    };
+    let documentation = match Function::from(fn_id).as_assoc_item(db)?.container(db) {
+        AssocItemContainer::Impl(impl_) => impl_.self_ty(db).as_adt()?.docs(db),
+        AssocItemContainer::Trait(trait_) => trait_.docs(db),
+    }?;
+    summary(documentation.as_str())
+}
+
+/// Collects how other types of the local crates implement the same trait method as `function`, skipping its own impl.
+fn sibling_implementations(function: Function, db: &RootDatabase) -> Vec<ContextSnippet> {
+    let Some(trait_) = function
+        .as_assoc_item(db)
+        .and_then(|item| item.implemented_trait(db))
+    else {
+        return vec![];
+    };
+    Impl::all_for_trait(db, trait_)
+        .into_iter()
+        .filter(|impl_| impl_.module(db).krate().origin(db).is_local())
+        .flat_map(|impl_| impl_.items(db))
+        .filter_map(|item| match item {
+            AssocItem::Function(f) if f != function && f.name(db) == function.name(db) => Some(f),
+            _ => None,
+        })
+        .filter_map(|f| {
+            let (file_id, range) = function_text_range(f.into(), db)?;
+            Some(ContextSnippet {
+                kind: SnippetKind::SiblingImpl,
+                range,
+                text: print_body(file_id, range, db)?,
+            })
+        })
+        .take(MAX_SIBLING_IMPLS)
+        .collect()
 }
 
 fn print_body(file_id: EditionedFileId, range: TextRange, db: &RootDatabase) -> Option<String> {
@@ -261,30 +1589,3 @@ fn print_body(file_id: EditionedFileId, range: TextRange, db: &RootDatabase) ->
         ra_ap_syntax::NodeOrToken::Token(t) => t.text().to_string(),
     })
 }
-
-/// Resolves a given `FunctionId` to its corresponding `TextRange` in the source code, but only if the function is defined within a local crate. It queries the `RootDatabase` to determine the function's origin and, if local, extracts its precise `TextRange`.
-///
-/// # Arguments
-/// * `db` - A reference to the `RootDatabase` (Rust Analyzer database).
-/// * `fn_id` - The `FunctionId` of the function to be resolved.
-///
-/// # Returns
-/// An `Option<TextRange>` containing the `TextRange` of the function if it is local and resolvable, otherwise `None`.
-fn return_functions(db: &RootDatabase, fn_id: FunctionId) -> Option<TextRange> {
-    let lookup = &db.lookup_intern_function(fn_id);
-    let as_syn = lookup.id;
-    if let Some(file_id) = as_syn.file_id.file_id() {
-        if db.relevant_crates(file_id.file_id(db))[0]
-            .data(db)
-            .origin
-            .is_local()
-        {
-            let range = as_syn.to_ptr(db).syntax_node_ptr().text_range();
-            Some(range)
-        } else {
-            None
-        }
-    } else {
-        None
-    }
-}
@@ -1,23 +1,26 @@
-use crate::analyzer::{AnalyzerData, contextualizer};
+use crate::analyzer::{
+    AnalyzerData, SnippetKind, contextualizer, dependency_context, externally_reachable,
+    function_usages, is_test_module, macro_expansions, macro_usages, rank_snippets, syntax_context,
+    trait_declaration, type_doc,
+};
 use ai_interactions::return_prompt;
 use clap::error::Result;
 use gemini::request_preparation::{Context, Metadata, Request, SingleFunctionData};
 use git_parsing::{Git2ErrorHandling, Hunk, get_easy_hunk, match_patch_with_parse};
 use git2::Diff;
 use glob::glob;
+use ra_ap_vfs::VfsPath;
 use rayon::prelude::*;
-use rust_parsing::ObjectRange;
-use rust_parsing::error::{ErrorBinding, InvalidIoOperationsSnafu};
+use rust_parsing::error::ErrorBinding;
 use rust_parsing::file_parsing::{FileExtractor, Files};
-use rust_parsing::rust_parser::{RustItemParser, RustParser};
 use rust_parsing::{self};
+use rust_parsing::{ObjectRange, ParseCache, Span, SpanExt, Visibility};
 use serde::{Deserialize, Serialize};
-use snafu::ResultExt;
 use std::{
     env, fs,
-    ops::Range,
     path::{Path, PathBuf},
 };
+use tracing::{Level, event};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct UseItem {
@@ -39,13 +42,13 @@ pub struct FullDiffInfo {
 }
 pub struct Difference {
     pub filename: PathBuf,
-    pub line: Vec<usize>,
+    pub spans: Vec<Span>,
 }
 
 #[derive(Debug, Clone)]
 struct LocalChange {
     filename: PathBuf,
-    range: Range<usize>,
+    span: Span,
 }
 #[derive(Debug, Clone)]
 pub struct LocalContext {
@@ -57,7 +60,7 @@ pub struct LocalContext {
 #[derive(Debug)]
 pub struct ChangeFromPatch {
     pub filename: PathBuf,
-    pub range: Vec<Range<usize>>,
+    pub spans: Vec<Span>,
 }
 
 pub fn is_file_allowed(file: &Path, exclusions: &[String]) -> Result<bool, ErrorBinding> {
@@ -94,15 +97,23 @@ pub fn changes_from_patch(
     rust_type: Vec<String>,
     rust_name: Vec<String>,
     file_exclude: &[String],
-    analyzer_data: AnalyzerData,
+    analyzer_data: Option<AnalyzerData>,
+    cache: &ParseCache,
+    workspace_root: &Path,
 ) -> Result<Vec<Request>, ErrorBinding> {
+    //With public_api_only, only the items that can be reached from outside of their crate are documented
+    let settings = return_prompt()?.patchdog_settings;
+    let reachable = analyzer_data
+        .as_ref()
+        .filter(|_| settings.public_api_only)
+        .map(externally_reachable);
     let tasks: Vec<LocalChange> = exported_from_file
         .par_iter()
         .flat_map(|each| {
-            each.range.par_iter().filter_map(move |obj| {
+            each.spans.par_iter().filter_map(move |obj| {
                 Some(LocalChange {
                     filename: each.filename.clone(),
-                    range: obj.clone(),
+                    span: obj.clone(),
                 })
             })
         })
@@ -112,12 +123,12 @@ pub fn changes_from_patch(
         .filter_map(|change| {
             //Here we only allow files, that are not in the config.yaml-Patchdog_settings-excluded_files
             if is_file_allowed(&change.filename, file_exclude).ok()? {
-                let source = fs::read_to_string(&change.filename).ok()?;
-                let parsed_object = RustItemParser::parse_rust_file(&change.filename)
-                    .ok()?
+                let parsed_file = cache.get(&change.filename).ok()?;
+                let parsed_object = parsed_file
+                    .object_ranges()
                     .iter()
                     .filter_map(|each| {
-                        if each.line_ranges == change.range {
+                        if each.span == change.span {
                             return Some(each.to_owned());
                         }
                         None
@@ -140,9 +151,7 @@ pub fn changes_from_patch(
                             .contains(obj_name_to_compare)
                 {
                     //At this point in parsed_file we are already aware of all the referenced data
-                    let fn_as_string = FileExtractor::string_to_vector(&source)
-                        [change.range.start - 1..change.range.end]
-                        .join("\n");
+                    let fn_as_string = parsed_file.lines(&change.span)?;
                     /*
                     Calling find_context(all methods: bla-bla, function: String) -> context(Vec<String>) {
                         1.
@@ -150,27 +159,185 @@ pub fn changes_from_patch(
                         3. Return matching structures
                     }
                     */
-                    let parse_analyzer = &RustItemParser::parse_result_items(&source).ok()?;
                     let mut lineranges = vec![];
-                    for each in parse_analyzer {
-                        let changed_syn = change.range.start..change.range.end;
-                        let linerange =
-                            RustItemParser::textrange_into_linerange(each.0.to_owned(), &source);
-                        if linerange == changed_syn {
+                    for each in &parsed_file.items {
+                        if *each.0 == change.span.text_range() {
                             lineranges.push(each.0);
                         }
                     }
-                    let analyzer_context = contextualizer(
-                        &change.filename,
-                        lineranges.first().copied(),
-                        &analyzer_data,
-                    )
-                    .par_iter()
-                    .map(|(_, value)| value.to_string())
-                    .collect::<Vec<String>>();
+                    //Items overlapping a syntax error come from a recovered tree, their lines can't be trusted
+                    if let Some(range) = lineranges.first() {
+                        let syntax_errors = parsed_file.syntax_errors_in(**range);
+                        if !syntax_errors.is_empty() {
+                            for error in syntax_errors {
+                                event!(
+                                    Level::WARN,
+                                    "Skipping {} at {}:{:?}, syntax error at lines {:?}: {}",
+                                    obj_name_to_compare,
+                                    change.filename.display(),
+                                    change.span.lines(),
+                                    error.span.lines(),
+                                    error.message
+                                );
+                            }
+                            return None;
+                        }
+                    }
+                    //Test functions and #[cfg(test)] code are skipped unless include_tests is set,
+                    //files included by `#[cfg(test)] mod tests;` are only known to the analyzer
+                    if !settings.include_tests
+                        && (lineranges
+                            .first()
+                            .and_then(|range| parsed_file.items.get(*range))
+                            .is_some_and(|item| item.is_test)
+                            || analyzer_data.as_ref().is_some_and(|analyzer_data| {
+                                is_test_module(&change.filename, analyzer_data)
+                            }))
+                    {
+                        return None;
+                    }
+                    if let Some(reachable) = &reachable {
+                        let vfs_path =
+                            VfsPath::new_real_path(change.filename.display().to_string());
+                        if !lineranges
+                            .first()
+                            .is_some_and(|range| reachable.contains(&(vfs_path, **range)))
+                        {
+                            return None;
+                        }
+                    } else if settings.public_api_only
+                        //Without the analyzer, only the declared visibility is known
+                        && !lineranges
+                            .first()
+                            .and_then(|range| parsed_file.items.get(*range))
+                            .is_some_and(|item| item.visibility == Visibility::Public)
+                    {
+                        return None;
+                    }
+                    let mut snippets = match &analyzer_data {
+                        Some(analyzer_data) => {
+                            let mut snippets = contextualizer(
+                                &change.filename,
+                                lineranges.first().copied(),
+                                analyzer_data,
+                            );
+                            //Functions and tests calling the changed one, to show how it is used
+                            if let Some(range) = lineranges.first() {
+                                snippets.extend(function_usages(
+                                    &change.filename,
+                                    **range,
+                                    analyzer_data,
+                                ));
+                            }
+                            //Opt-in signatures and docs of the dependency and std items it refers to
+                            if settings.dependency_docs
+                                && let Some(range) = lineranges.first()
+                            {
+                                snippets.extend(dependency_context(
+                                    &change.filename,
+                                    **range,
+                                    analyzer_data,
+                                    settings.dependency_docs_budget,
+                                ));
+                            }
+                            snippets
+                        }
+                        //Syntax-only mode, context is limited to the items of the same file
+                        None => lineranges
+                            .first()
+                            .map(|range| syntax_context(&parsed_file, **range))
+                            .unwrap_or_default(),
+                    };
+                    //Context is reduced to signatures and docs before it is measured against the budget
+                    if settings.context_rendering == "skeleton" {
+                        snippets = snippets
+                            .into_iter()
+                            .map(|snippet| {
+                                snippet
+                                    .skeletonize(settings.full_body_threshold, parsed_file.edition)
+                            })
+                            .collect();
+                    }
+                    //Most relevant snippets first, trimmed to the per-item context budget
+                    let mut external_dependencies = vec![];
+                    let mut usages = vec![];
+                    let mut test_examples = vec![];
+                    for snippet in rank_snippets(snippets, settings.context_budget) {
+                        match snippet.kind {
+                            SnippetKind::Caller => usages.push(snippet.text),
+                            SnippetKind::Test => test_examples.push(snippet.text),
+                            _ => external_dependencies.push(snippet.text),
+                        }
+                    }
+                    //Trait methods carry the declaration they implement
+                    let trait_declaration = lineranges.first().and_then(|range| {
+                        trait_declaration(&change.filename, **range, analyzer_data.as_ref()?)
+                    });
+                    //Docs, imports and sibling signatures of the enclosing module, keyed by file and module path.
+                    //The file is relative to the workspace root, so paths of the machine running patchdog stay out of the prompt
+                    let module_context = lineranges
+                        .first()
+                        .and_then(|range| parsed_file.module_context(**range));
+                    let module_file = change
+                        .filename
+                        .strip_prefix(workspace_root)
+                        .ok()
+                        .or_else(|| change.filename.file_name().map(Path::new))
+                        .unwrap_or(&change.filename);
+                    let module = module_context
+                        .as_ref()
+                        .map(|module_context| {
+                            std::iter::once(module_file.display().to_string())
+                                .chain(module_context.path.iter().cloned())
+                                .collect::<Vec<String>>()
+                                .join("::")
+                        })
+                        .unwrap_or_default();
+                    //Methods carry the type or trait they belong to, the doc of a type declared elsewhere needs the analyzer
+                    let enclosing_type = lineranges
+                        .first()
+                        .and_then(|range| parsed_file.enclosing_type(**range))
+                        .unwrap_or_default();
+                    let class_doc = enclosing_type.doc.or_else(|| {
+                        let range = lineranges.first()?;
+                        type_doc(&change.filename, **range, analyzer_data.as_ref()?)
+                    });
+                    let signature = lineranges
+                        .first()
+                        .and_then(|range| parsed_file.function_signature(**range));
+                    //macro_rules! items carry their arms and invocations found in the workspace
+                    let macro_rules = lineranges.first().and_then(|range| {
+                        let mut definition = parsed_file.macro_definition(**range)?;
+                        if let Some(analyzer_data) = &analyzer_data {
+                            definition.usages =
+                                macro_usages(&change.filename, **range, analyzer_data);
+                        }
+                        Some(definition)
+                    });
+                    //Opt-in expansions of the macro calls in the item, only rust-analyzer can expand them
+                    let macro_expansions = match (&analyzer_data, lineranges.first()) {
+                        (Some(analyzer_data), Some(range))
+                            if settings.macro_expansion_budget > 0 =>
+                        {
+                            macro_expansions(
+                                &change.filename,
+                                **range,
+                                analyzer_data,
+                                settings.macro_expansion_budget,
+                            )
+                        }
+                        _ => vec![],
+                    };
                     let context = Context {
-                        class_name: "".to_string(),
-                        external_dependencies: analyzer_context,
+                        class_name: enclosing_type.class_name,
+                        class_doc,
+                        external_dependencies,
+                        usages,
+                        test_examples,
+                        trait_declaration,
+                        module,
+                        module_context: module_context.map(|module_context| module_context.text),
+                        macro_expansions,
                         old_comment: vec![],
                     };
                     Some(Request {
@@ -178,10 +345,12 @@ pub fn changes_from_patch(
                         data: SingleFunctionData {
                             function_text: fn_as_string.to_string(),
                             fn_name: obj_name_to_compare.clone(),
+                            signature,
+                            macro_rules,
                             context,
                             metadata: Metadata {
                                 filepath: change.filename.clone(),
-                                line_range: change.range.clone(),
+                                span: change.span.clone(),
                             },
                         },
                     })
@@ -197,15 +366,22 @@ pub fn changes_from_patch(
 }
 
 /// Processes a Git patch file to extract structured information about code changes, specifically identifying modified objects and their line ranges. It resolves the provided relative patch path, then delegates to `get_patch_data` to parse the patch and convert its contents into a vector of `ChangeFromPatch` structs.
+/// The files named in the patch are resolved against the workspace root, so patchdog can be run from any directory.
 ///
 /// # Arguments
 /// * `path_to_patch` - A `PathBuf` representing the path to the Git patch file, relative to the current working directory.
+/// * `workspace_root` - The directory of the analyzed `Cargo.toml`, which the paths inside the patch are relative to.
+/// * `cache` - The per-run `ParseCache`, shared by every stage that needs a parsed file.
 ///
 /// # Returns
 /// A `Result<Vec<ChangeFromPatch>, ErrorBinding>` containing a vector of `ChangeFromPatch` structs, each detailing filenames and ranges of changes, or an `ErrorBinding` if any file system or patch parsing error occurs.
-pub fn patch_data_argument(path_to_patch: PathBuf) -> Result<Vec<ChangeFromPatch>, ErrorBinding> {
+pub fn patch_data_argument(
+    path_to_patch: PathBuf,
+    workspace_root: PathBuf,
+    cache: &ParseCache,
+) -> Result<Vec<ChangeFromPatch>, ErrorBinding> {
     let path = env::current_dir()?;
-    let patch = get_patch_data(path.join(path_to_patch), path)?;
+    let patch = get_patch_data(path.join(path_to_patch), workspace_root, cache)?;
     Ok(patch)
 }
 
@@ -216,6 +392,7 @@ pub fn patch_data_argument(path_to_patch: PathBuf) -> Result<Vec<ChangeFromPatch
 ///
 /// * `path_to_patch` - A `PathBuf` pointing to the patch file.
 /// * `relative_path` - A `PathBuf` indicating the base directory to resolve file paths within the patch.
+/// * `cache` - The per-run `ParseCache` the changed files are parsed through.
 ///
 /// # Returns
 ///
@@ -228,25 +405,29 @@ and an according path each those ranges that has to be iterated only once
 pub fn get_patch_data(
     path_to_patch: PathBuf,
     relative_path: PathBuf,
+    cache: &ParseCache,
 ) -> Result<Vec<ChangeFromPatch>, ErrorBinding> {
-    let export = patch_export_change(path_to_patch, relative_path)?;
+    let export = patch_export_change(path_to_patch, relative_path, cache)?;
     let export_difference = export
         .par_iter()
         .flat_map(|difference| {
-            let parsed = RustItemParser::parse_rust_file(&difference.filename).ok()?;
+            let parsed = cache.get(&difference.filename).ok()?.object_ranges();
             let vector_of_changed = parsed
                 .par_iter()
                 .flat_map(|each_parsed| {
-                    let range = each_parsed.line_start()..each_parsed.line_end();
-                    if difference.line.par_iter().any(|line| range.contains(line)) {
-                        Some(range)
+                    if difference
+                        .spans
+                        .par_iter()
+                        .any(|span| each_parsed.span.overlaps(span))
+                    {
+                        Some(each_parsed.span.clone())
                     } else {
                         None
                     }
                 })
                 .collect();
             Some(ChangeFromPatch {
-                range: vector_of_changed,
+                spans: vector_of_changed,
                 filename: difference.filename.to_owned(),
             })
         })
@@ -257,21 +438,26 @@ pub fn get_patch_data(
 fn store_objects(
     relative_path: &Path,
     patch_src: &[u8],
+    cache: &ParseCache,
 ) -> Result<Vec<FullDiffInfo>, Git2ErrorHandling> {
     let diff = Diff::from_buffer(patch_src)?;
     let changes = &match_patch_with_parse(relative_path, &diff)?;
     let vec_of_surplus = changes
         .iter()
-        .filter_map(|change| {
-            let list_of_unique_files = get_easy_hunk(&diff, &change.filename()).ok()?;
-            let path = relative_path.join(change.filename());
-            let file = fs::read_to_string(&path)
-                .context(InvalidIoOperationsSnafu { path })
-                .ok()?;
-            let parsed = RustItemParser::parse_all_rust_items(&file).ok()?;
+        .filter_map(|filename| {
+            let path = relative_path.join(filename);
+            let parsed_file = cache.get(&path).ok()?;
+            //Hunks are located in the same contents the items were parsed from
+            let list_of_unique_files = get_easy_hunk(
+                &diff,
+                filename,
+                parsed_file.file_id,
+                &parsed_file.line_index,
+            )
+            .ok()?;
             Some(FullDiffInfo {
-                name: change.filename(),
-                object_range: parsed,
+                name: filename.to_owned(),
+                object_range: parsed_file.all_objects(),
                 hunk: list_of_unique_files,
             })
         })
@@ -280,12 +466,13 @@ fn store_objects(
 }
 
 /// Parses a patch file to identify changed lines within Rust code objects and associates them with their respective files. This function reads the patch, extracts diff hunks, and then iterates through relevant Rust files to determine which `ObjectRange` items (e.g., functions, structs) are affected by the changes.
-/// It ultimately returns a structured list of `Difference` objects, each containing a filename and a vector of line numbers that have been modified.
+/// It ultimately returns a structured list of `Difference` objects, each containing a filename and the `Span`s of the lines that have been modified.
 ///
 /// # Arguments
 ///
 /// * `path_to_patch` - A `PathBuf` pointing to the patch file to be analyzed.
 /// * `relative_path` - A `PathBuf` representing the base directory for resolving file paths mentioned in the patch.
+/// * `cache` - The per-run `ParseCache` the changed files are parsed through.
 ///
 /// # Returns
 ///
@@ -293,36 +480,26 @@ fn store_objects(
 fn patch_export_change(
     path_to_patch: PathBuf,
     relative_path: PathBuf,
+    cache: &ParseCache,
 ) -> Result<Vec<Difference>, ErrorBinding> {
-    let mut change_in_line: Vec<usize> = Vec::new();
+    let mut change_in_line: Vec<Span> = Vec::new();
     let mut line_and_file: Vec<Difference> = Vec::new();
     let patch_text = fs::read(&path_to_patch)?;
-    let each_diff = store_objects(&relative_path, &patch_text)?;
+    let each_diff = store_objects(&relative_path, &patch_text, cache)?;
     for diff_hunk in &each_diff {
         let path_to_file = relative_path.to_owned().join(&diff_hunk.name);
-        let file = fs::read_to_string(&path_to_file).context(InvalidIoOperationsSnafu {
-            path: &path_to_file,
-        })?;
-        let parsed = RustItemParser::parse_result_items(&file)?
-            .par_iter()
-            .map(|val| {
-                let range = RustItemParser::textrange_into_linerange(*val.0, &file);
-                ObjectRange {
-                    line_ranges: range,
-                    names: val.1.names.clone(),
-                }
-            })
-            .collect::<Vec<ObjectRange>>();
+        let parsed_file = cache.get(&path_to_file)?;
+        let parsed = parsed_file.object_ranges();
         for each in &diff_hunk.hunk {
             let parsed_in_diff = &parsed;
-            if FileExtractor::check_for_valid_object(parsed_in_diff, each.get_line())? {
+            if FileExtractor::check_for_valid_object(parsed_in_diff, &each.span)? {
                 continue;
             }
-            change_in_line.push(each.get_line());
+            change_in_line.push(each.span.clone());
         }
         line_and_file.push(Difference {
             filename: path_to_file,
-            line: change_in_line.to_owned(),
+            spans: change_in_line.to_owned(),
         });
         change_in_line.clear();
     }
@@ -1,4 +1,4 @@
-use crate::analyzer::AnalyzerData;
+use crate::analyzer::init_analyzer;
 use crate::binding::{self, changes_from_patch};
 use clap::ArgGroup;
 use clap::Parser;
@@ -8,6 +8,7 @@ use gemini::request_preparation::{RawResponse, SingleFunctionData, WaitForTimeou
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
 use regex::Regex;
+use rust_parsing::ParseCache;
 use rust_parsing::error::ErrorBinding;
 use rust_parsing::error::ErrorHandling;
 use rust_parsing::error::InvalidIoOperationsSnafu;
@@ -17,7 +18,10 @@ use serde::Deserialize;
 use serde::Serialize;
 use snafu::ResultExt;
 use std::collections::HashMap;
-use std::{env, fs, path::PathBuf};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
 use tracing::{Level, event};
 
 #[derive(Parser, Debug)]
@@ -33,6 +37,44 @@ pub struct Mode {
     name_rust: Vec<String>,
     #[arg(long, default_value = "false")]
     pub enable_debug: bool,
+    /// Path to the `Cargo.toml` of the analyzed workspace, `Cargo.toml` in the current directory by default
+    #[arg(long)]
+    manifest_path: Option<PathBuf>,
+    /// Workspace members to analyze, all of them by default
+    #[arg(short, long, num_args = 1..)]
+    pub package: Vec<String>,
+}
+
+impl Mode {
+    /// Returns the canonical path of the analyzed `Cargo.toml`: `--manifest-path` resolved against the current directory,
+    /// or `Cargo.toml` in the current directory when it isn't given. Canonicalizing removes `..` and symlinks, so the
+    /// files of the patch and the workspace members compare equal to the paths the analyzer loads. A manifest that doesn't
+    /// exist, e.g. in a `rust-project.json` project, is returned as given and left to `init_analyzer` to report.
+    ///
+    /// # Returns
+    /// A `Result<PathBuf, ErrorHandling>` with the manifest path, or an `ErrorHandling` if the current directory can't be read.
+    pub fn manifest(&self) -> Result<PathBuf, ErrorHandling> {
+        let dir = env::current_dir()?;
+        let manifest = dir.join(
+            self.manifest_path
+                .as_deref()
+                .unwrap_or(Path::new("Cargo.toml")),
+        );
+        Ok(fs::canonicalize(&manifest).unwrap_or(manifest))
+    }
+
+    /// Returns the directory of the analyzed `Cargo.toml`, against which the files of the patch and `excluded_files` are resolved.
+    /// Without a manifest the current directory is used, so patchdog still runs with syntax-only context.
+    ///
+    /// # Returns
+    /// A `Result<PathBuf, ErrorHandling>` with the workspace root, or an `ErrorHandling` if the current directory can't be read.
+    pub fn workspace_root(&self) -> Result<PathBuf, ErrorHandling> {
+        let manifest = self.manifest()?;
+        match manifest.parent() {
+            Some(root) if manifest.is_file() => Ok(root.to_path_buf()),
+            _ => Ok(env::current_dir()?),
+        }
+    }
 }
 #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
 struct LinkedResponse {
@@ -48,36 +90,60 @@ pub struct ResponseForm {
 }
 
 /// Orchestrates the entire process of applying a patch to a codebase, generating AI-based code suggestions, and writing them back to files. This asynchronous function takes a patch file, analyzes the changes, filters them based on exclusion rules, and then generates `Request` objects for an AI agent.
+/// The patch is read before anything else: the analyzer is only initialized when it touches Rust items, and only for the crates owning them.
 /// It then calls the AI agent, collects the responses, and writes the suggested new comments or code modifications to the corresponding files. The function includes a call limit to prevent infinite recursion in case of persistent AI failures.
 ///
 /// # Arguments
 ///
-/// * `analyzer_data` - `AnalyzerData` providing context for code analysis.
 /// * `commands` - `Mode` struct containing command-line arguments, including the patch file path, target Rust types, and names, and debug flag.
 ///
 /// # Returns
 ///
 /// A `Result<(), ErrorBinding>` indicating the success or failure of the entire patch-to-agent and write-back process.
-pub async fn cli_patch_to_agent(
-    analyzer_data: AnalyzerData,
-    commands: Mode,
-) -> Result<(), ErrorBinding> {
+pub async fn cli_patch_to_agent(commands: Mode) -> Result<(), ErrorBinding> {
     //Mode accepts type and name of the object for the sake of debugging. It autodefaults to any fn
-    let patch = binding::patch_data_argument(commands.file_patch)?;
+    let cache = ParseCache::default();
+    let dir = commands.workspace_root()?;
+    let patch = binding::patch_data_argument(commands.file_patch.clone(), dir.clone(), &cache)?;
+    //The analyzer is only loaded once the patch is known to touch Rust items, and only for the crates owning them
+    let changed_files = patch
+        .iter()
+        .filter(|change| !change.spans.is_empty())
+        .map(|change| change.filename.clone())
+        .collect::<Vec<PathBuf>>();
+    if changed_files.is_empty() {
+        event!(Level::INFO, "No Rust items changed, skipping analysis");
+        return Ok(());
+    }
+    //Without a loadable workspace patchdog keeps going with syntax-only context
+    let analyzer_data = init_analyzer(
+        commands.manifest()?,
+        commands.package.clone(),
+        changed_files,
+    )
+    .inspect_err(|err| {
+        event!(
+            Level::WARN,
+            "Semantic context unavailable, falling back to syntax-only context: {err}"
+        )
+    })
+    .ok();
     let parameters = ai_interactions::return_prompt()?.patchdog_settings;
     event!(Level::INFO, "type: {:#?}", parameters.affected_object_types);
-    let dir = env::current_dir()?;
     let excluded_paths = parameters
         .excluded_files
         .par_iter()
         .map(|path| dir.join(path).display().to_string())
         .collect::<Vec<String>>();
+    let semantic_context = analyzer_data.is_some();
     let request = changes_from_patch(
         patch,
         parameters.affected_object_types,
         commands.name_rust,
         &excluded_paths,
         analyzer_data,
+        &cache,
+        &dir,
     )?;
     println!("request: {:#?}", request);
     //Here occurs check for pending changes
@@ -95,6 +161,12 @@ pub async fn cli_patch_to_agent(
         );
         write_to_file(responses_collected)?;
     }
+    if !semantic_context {
+        event!(
+            Level::WARN,
+            "Semantic context was unavailable, context was limited to items of the same file"
+        );
+    }
     Ok(())
 }
 
@@ -128,10 +200,13 @@ pub async fn call(
         for matched in matches {
             let clear_element = pool_of_requests.remove(&matched.data.uuid).ok_or("None");
             match clear_element {
-                Ok(ok) => responses_collected.push(ResponseForm {
-                    data: ok.to_owned(),
-                    new_comment: matched.new_comment,
-                }),
+                Ok(ok) => {
+                    review_comment(&ok, &matched.new_comment);
+                    responses_collected.push(ResponseForm {
+                        data: ok.to_owned(),
+                        new_comment: matched.new_comment,
+                    })
+                }
                 Err(_) => continue,
             }
         }
@@ -155,6 +230,28 @@ pub async fn call(
     }
 }
 
+/// Checks a generated comment against the signature of the function it documents and logs every fact it leaves out,
+/// such as undocumented arguments or a missing `# Errors` section for a function returning `Result`.
+///
+/// # Arguments
+///
+/// * `data` - The `SingleFunctionData` the comment was generated for.
+/// * `comment` - The generated comment.
+fn review_comment(data: &SingleFunctionData, comment: &str) {
+    if let Some(signature) = &data.signature {
+        let missing = signature.missing_in_comment(comment);
+        if !missing.is_empty() {
+            event!(
+                Level::WARN,
+                "Comment for {} in {} does not mention: {}",
+                data.fn_name,
+                data.metadata.filepath.display(),
+                missing.join(", ")
+            );
+        }
+    }
+}
+
 /// Extracts `RawResponse` objects from a raw string response using a predefined regular expression. This function is designed to robustly parse potentially messy or concatenated JSON responses, isolating each valid JSON object that matches the `REGEX` pattern.
 /// It iterates through all matches found by the regex and attempts to deserialize each captured string into a `RawResponse` struct. This is useful for processing responses from external APIs that might not always return perfectly formed JSON arrays.
 ///
@@ -336,13 +433,7 @@ fn fallback_repair(output: Vec<String>) -> Result<Vec<RawResponse>, ErrorHandlin
 /// A `Result<(), ErrorHandling>` indicating success or failure of the write operations.
 pub fn write_to_file(response: Vec<ResponseForm>) -> Result<(), ErrorHandling> {
     let mut response = response;
-    response.sort_by(|a, b| {
-        b.data
-            .metadata
-            .line_range
-            .start
-            .cmp(&a.data.metadata.line_range.start)
-    });
+    response.sort_by(|a, b| b.data.metadata.span.start.cmp(&a.data.metadata.span.start));
     event!(Level::INFO, "Quantity of responses: {}", response.len());
     //Typical representation of file as vector of lines
     for each in response {
@@ -352,7 +443,7 @@ pub fn write_to_file(response: Vec<ResponseForm>) -> Result<(), ErrorHandling> {
         FileExtractor::write_to_vecstring(
             path,
             as_vec,
-            each.data.metadata.line_range.start,
+            each.data.metadata.span.start.line,
             each.new_comment,
         )?;
     }
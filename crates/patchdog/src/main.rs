@@ -1,4 +1,3 @@
-use crate::analyzer::init_analyzer;
 use crate::cli::cli_patch_to_agent;
 use clap::Parser;
 use opentelemetry::trace::TracerProvider;
@@ -16,7 +15,7 @@ pub mod cli;
 #[cfg(test)]
 pub mod tests;
 
-/// The main entry point of the application, executed asynchronously. This function parses command-line arguments, sets up tracing for debugging if enabled, initializes the code analyzer, and loads environment variables.
+/// The main entry point of the application, executed asynchronously. This function parses command-line arguments, sets up tracing for debugging if enabled, and loads environment variables.
 /// It then delegates the core logic of processing patches and interacting with an AI agent to the `cli_patch_to_agent` function. The `tokio::main` attribute allows it to run asynchronous code.
 ///
 /// # Returns
@@ -28,9 +27,8 @@ async fn main() -> Result<(), ErrorBinding> {
     if commands.enable_debug {
         setup_tracing();
     }
-    let analyzer_data = init_analyzer();
     dotenv::dotenv().ok();
-    cli_patch_to_agent(analyzer_data, commands).await?;
+    cli_patch_to_agent(commands).await?;
     Ok(())
 }
 
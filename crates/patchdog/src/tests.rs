@@ -1,38 +1,83 @@
 mod tests {
+    use crate::analyzer::{
+        AnalyzerData, AnalyzerIndex, ContextSnippet, SnippetKind, contextualizer,
+        dependency_context, externally_reachable, function_usages, is_test_module,
+        macro_expansions, rank_snippets, syntax_context, trait_declaration, type_doc,
+    };
     use crate::cli::cherrypick_response;
     use gemini::request_preparation::RawResponse;
+    use git_parsing::get_easy_hunk;
+    use ra_ap_ide::{Edition, TextRange};
+    use ra_ap_load_cargo::{LoadCargoConfig, ProcMacroServerChoice, load_workspace_at};
+    use ra_ap_project_model::{CargoConfig, RustLibSource};
+    use ra_ap_syntax::AstNode;
+    use ra_ap_vfs::VfsPath;
     use regex::Regex;
-    use rust_parsing::ErrorHandling;
+    use rust_parsing::edition::{FALLBACK_EDITION, edition_for_file};
     use rust_parsing::error::InvalidIoOperationsSnafu;
+    use rust_parsing::expansion::render_expansion;
     use rust_parsing::file_parsing::{FileExtractor, Files, REGEX};
     use rust_parsing::object_range::Name;
+    use rust_parsing::skeleton::skeleton;
+    use rust_parsing::{
+        CommentKind, ErrorHandling, FileId, LineCol, LineIndex, ParseCache, ParsedFile, Span,
+        SpanExt, Visibility,
+    };
     use rust_parsing::{
         ObjectRange,
         rust_parser::{RustItemParser, RustParser},
     };
     use snafu::ResultExt;
     use std::collections::HashMap;
-    use std::{env, fs, path::Path};
+    use std::sync::Arc;
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+    };
     const PATH_BASE: &str = "../../tests/data.rs";
 
+    fn parse(src: &str) -> ParsedFile {
+        ParsedFile::new(
+            FileId::default(),
+            Path::new("lib.rs"),
+            src.to_string(),
+            FALLBACK_EDITION,
+        )
+        .unwrap()
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("patchdog-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn item_range(parsed: &ParsedFile, name: &str) -> TextRange {
+        parsed
+            .items
+            .values()
+            .find(|item| item.names.name == name)
+            .unwrap()
+            .range
+    }
+
     #[test]
     fn test_parser() {
         let src = fs::read_to_string(PATH_BASE).unwrap();
-        let analyzer = RustItemParser::parse_result_items(&src)
+        let edition = edition_for_file(Path::new(PATH_BASE));
+        let analyzer = RustItemParser::parse_result_items(&src, edition)
             .unwrap()
             .into_iter()
             .map(|val| {
-                let range = RustItemParser::textrange_into_linerange(val.0.clone(), &src);
+                let range = RustItemParser::textrange_into_span(val.0.clone(), &src).lines();
                 (range.to_owned(), val.1.clone())
             })
-            .collect::<HashMap<std::ops::Range<usize>, rust_parsing::rust_parser::AnalyzerRange>>();
-        let parser = RustItemParser::parse_all_rust_items(&src)
+            .collect::<HashMap<std::ops::RangeInclusive<usize>, rust_parsing::rust_parser::AnalyzerRange>>();
+        let parser = RustItemParser::parse_all_rust_items(&src, edition)
             .unwrap()
             .iter()
             .filter_map(|val| {
-                if val.names.type_name == "LifetimeIndicator"
-                    || val.names.type_name == "LineComment"
-                {
+                if val.names.type_name.ends_with("Comment") {
                     None
                 } else {
                     Some(val.clone())
@@ -49,7 +94,8 @@ mod tests {
     #[test]
     fn test_parse() {
         let source = fs::read_to_string(PATH_BASE).expect("File read failed");
-        let parsed = RustItemParser::parse_all_rust_items(&source).expect("Parsing failed");
+        let parsed = RustItemParser::parse_all_rust_items(&source, FALLBACK_EDITION)
+            .expect("Parsing failed");
         for object in parsed {
             let obj_type = object.names.type_name.clone();
             if obj_type == "impl".to_string() {}
@@ -61,7 +107,8 @@ mod tests {
         let source = fs::read_to_string(PATH_BASE)
             .context(InvalidIoOperationsSnafu { path: PATH_BASE })
             .expect("Failed to read file");
-        let parsed = RustItemParser::parse_all_rust_items(&source).expect("Failed to parse");
+        let parsed = RustItemParser::parse_all_rust_items(&source, FALLBACK_EDITION)
+            .expect("Failed to parse");
         for object in parsed {
             let obj_type = object.names.type_name.clone();
             if obj_type == "fn".to_string() {
@@ -88,12 +135,286 @@ mod tests {
     #[test]
     fn find_comments() {
         //block is of 94 symbols length
-        let file = fs::read_to_string("../../crates/patchdog/src/binding.rs").expect("err on 159");
-        let parsed = RustItemParser::parse_all_rust_items(&file).expect("err");
+        let path = Path::new("../../crates/patchdog/src/binding.rs");
+        let file = fs::read_to_string(path).expect("err on 159");
+        let parsed =
+            RustItemParser::parse_all_rust_items(&file, edition_for_file(path)).expect("err");
         for each in parsed {
             println!("{:?}", each);
         }
     }
+    #[test]
+    fn test_parse_cache() {
+        let cache = ParseCache::default();
+        let first = cache.get(Path::new(PATH_BASE)).unwrap();
+        let second = cache.get(Path::new(PATH_BASE)).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.object_ranges().len(), first.items.len());
+        //Files are read once per run, changes on disk are only picked up after invalidating
+        let dir = temp_dir();
+        let path = dir.join("lib.rs");
+        fs::write(&path, "fn a() {}").unwrap();
+        let before = cache.get(&path).unwrap();
+        fs::write(
+            &path,
+            "fn a() {}
+fn b() {}",
+        )
+        .unwrap();
+        assert!(Arc::ptr_eq(&before, &cache.get(&path).unwrap()));
+        cache.invalidate(&path);
+        let after = cache.get(&path).unwrap();
+        assert_eq!(after.items.len(), 2);
+        assert_eq!(after.file_id, before.file_id);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_span() {
+        let src = "fn a() {}\n\n    fn b() {\n    }\n";
+        let index = LineIndex::new(src);
+        let items = RustItemParser::parse_result_items(src, FALLBACK_EDITION).unwrap();
+        let b = items
+            .values()
+            .find(|item| item.names.name == "b")
+            .unwrap()
+            .range;
+        let span = index.span(FileId(1), b);
+        assert_eq!(span.start, LineCol { line: 3, col: 5 });
+        assert_eq!(span.end, LineCol { line: 4, col: 6 });
+        assert_eq!(span.lines(), 3..=4);
+        assert!(span.lines().contains(&4));
+        assert_eq!(span.text_range(), b);
+        let hunk_line = index.line_span(FileId(1), 4).unwrap();
+        assert!(span.overlaps(&hunk_line));
+        assert!(!span.overlaps(&index.line_span(FileId(1), 2).unwrap()));
+        assert!(!span.overlaps(&index.line_span(FileId(2), 4).unwrap()));
+        //Non-empty spans that only touch don't overlap, an empty span overlaps the spans it touches
+        assert!(
+            !index
+                .span(FileId(1), 0..9)
+                .overlaps(&index.span(FileId(1), 9..15))
+        );
+        assert!(
+            index
+                .span(FileId(1), 0..9)
+                .overlaps(&index.span(FileId(1), 8..15))
+        );
+        assert!(
+            index
+                .span(FileId(1), 9..9)
+                .overlaps(&index.span(FileId(1), 0..9))
+        );
+        //Hunks carry the span of their line in the new contents of the file
+        let patch = "diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -3,1 +3,2 @@\n     fn b() {\n+    }\n";
+        let diff = git2::Diff::from_buffer(patch.as_bytes()).unwrap();
+        let hunks = get_easy_hunk(&diff, "src/lib.rs", FileId(1), &index).unwrap();
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[1].span, hunk_line);
+        assert!(span.overlaps(&hunks[0].span));
+    }
+
+    #[test]
+    fn test_edition_detection() {
+        assert_eq!(
+            edition_for_file(Path::new("src/binding.rs")),
+            Edition::Edition2024
+        );
+        // `async` is a plain identifier before the 2018 edition
+        let items = RustItemParser::parse_result_items("fn async() {}", Edition::Edition2015)
+            .unwrap()
+            .into_values()
+            .map(|item| item.names.name)
+            .collect::<Vec<String>>();
+        assert_eq!(items, vec!["async".to_string()]);
+        //Every package of a fixture workspace gets the edition of its own manifest
+        let dir = temp_dir();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"*\"]\n\n[workspace.package]\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        let package = |name: &str, edition: &str| {
+            fs::create_dir_all(dir.join(name).join("src")).unwrap();
+            fs::write(
+                dir.join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n{edition}"),
+            )
+            .unwrap();
+            edition_for_file(&dir.join(name).join("src").join("lib.rs"))
+        };
+        assert_eq!(package("missing", ""), Edition::Edition2015);
+        assert_eq!(
+            package("explicit_2018", "edition = \"2018\"\n"),
+            Edition::Edition2018
+        );
+        assert_eq!(
+            package("explicit_2021", "edition = \"2021\"\n"),
+            Edition::Edition2021
+        );
+        assert_eq!(
+            package("inherited", "edition.workspace = true\n"),
+            Edition::Edition2021
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let src = "fn intact() {}\n\nfn broken(x: u32 {\n    x\n}\n";
+        let parsed = parse(src);
+        assert!(!parsed.syntax_errors.is_empty());
+        for item in parsed.items.values() {
+            let overlaps = !parsed.syntax_errors_in(item.range).is_empty();
+            assert_eq!(overlaps, item.names.name == "broken");
+        }
+    }
+
+    #[test]
+    fn test_macro_definition() {
+        let src = r#"macro_rules! hashmap {
+    () => { HashMap::new() };
+    ($($key:expr => $value:expr),+ $(,)?) => {{
+        let mut map = HashMap::new();
+        $(map.insert($key, $value);)+
+        map
+    }};
+}"#;
+        let parsed = parse(src);
+        let range = *parsed.items.keys().next().unwrap();
+        let definition = parsed.macro_definition(range).unwrap();
+        assert_eq!(definition.name, "hashmap");
+        assert_eq!(definition.arms.len(), 2);
+        assert!(definition.arms[0].fragments.is_empty());
+        let fragments = definition.arms[1]
+            .fragments
+            .iter()
+            .map(|fragment| (fragment.name.as_str(), fragment.kind.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(fragments, vec![("key", "expr"), ("value", "expr")]);
+        assert!(parsed.function_signature(range).is_none());
+    }
+
+    #[test]
+    fn test_function_signature() {
+        let src = "pub async fn load<'a, T: Clone + Send>(&self, path: &'a Path, items: Vec<T>) -> Result<Vec<T>, ErrorBinding> where T: Debug { todo!() }";
+        let parsed = parse(src);
+        let range = *parsed.items.keys().next().unwrap();
+        let signature = parsed.function_signature(range).unwrap();
+        assert_eq!(signature.qualifiers, vec!["async".to_string()]);
+        assert_eq!(signature.self_kind.as_deref(), Some("&self"));
+        assert_eq!(signature.generics[1].name, "T");
+        assert_eq!(signature.generics[1].bounds, vec!["Clone", "Send"]);
+        assert_eq!(signature.where_clause, vec!["T: Debug"]);
+        assert_eq!(signature.fn_input.len(), 2);
+        assert_eq!(signature.fn_input[0].input_type, "&'a Path");
+        assert_eq!(signature.fn_out.kind, "result");
+        assert_eq!(signature.fn_out.output_type, "Vec<T>");
+        assert_eq!(signature.fn_out.error_type.as_deref(), Some("ErrorBinding"));
+        assert_eq!(
+            signature.missing_in_comment("Loads `items` from `path`."),
+            vec!["# Errors section".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_visibility() {
+        let src = "pub fn a() {}\npub(crate) fn b() {}\nfn c() {}\npub(in crate::cli) fn d() {}\n#[macro_export]\nmacro_rules! e { () => {} }\nmacro_rules! f { () => {} }";
+        let mut items = RustItemParser::parse_result_items(src, FALLBACK_EDITION)
+            .unwrap()
+            .into_values()
+            .map(|item| (item.names.name, item.visibility))
+            .collect::<Vec<(String, Visibility)>>();
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            items,
+            vec![
+                ("a".to_string(), Visibility::Public),
+                ("b".to_string(), Visibility::Crate),
+                ("c".to_string(), Visibility::Private),
+                (
+                    "d".to_string(),
+                    Visibility::InPath("crate::cli".to_string())
+                ),
+                ("e".to_string(), Visibility::Public),
+                ("f".to_string(), Visibility::Private),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_test_code_detection() {
+        let src = r#"fn lib() {}
+#[test]
+fn unit() {}
+#[tokio::test]
+async fn runtime() {}
+#[cfg(not(test))]
+fn release() {}
+#[cfg(test)]
+mod tests {
+    fn helper() {}
+}
+"#;
+        let mut items = RustItemParser::parse_result_items(src, FALLBACK_EDITION)
+            .unwrap()
+            .into_values()
+            .map(|item| (item.names.name, item.is_test))
+            .collect::<Vec<(String, bool)>>();
+        items.sort();
+        assert_eq!(
+            items,
+            vec![
+                ("helper".to_string(), true),
+                ("lib".to_string(), false),
+                ("release".to_string(), false),
+                ("runtime".to_string(), true),
+                ("tests".to_string(), true),
+                ("unit".to_string(), true),
+            ]
+        );
+        let file_level = "#![cfg(test)]\nfn only_in_tests() {}";
+        assert!(
+            RustItemParser::parse_result_items(file_level, FALLBACK_EDITION)
+                .unwrap()
+                .values()
+                .all(|item| item.is_test)
+        );
+    }
+
+    #[test]
+    fn test_comment_model() {
+        let src = r#"//! Module docs
+/* block
+   spanning lines */
+const URL: &str = "http://example.com"; // trailing
+
+/// Documents `lifetimes`
+#[inline]
+fn lifetimes<'a>(input: &'a str) -> &'a str {
+    input
+}
+"#;
+        let comments = RustItemParser::parse_comments(src, FALLBACK_EDITION).unwrap();
+        let kinds = comments
+            .iter()
+            .map(|comment| comment.kind)
+            .collect::<Vec<CommentKind>>();
+        assert_eq!(
+            kinds,
+            vec![
+                CommentKind::InnerDoc,
+                CommentKind::Block,
+                CommentKind::Line,
+                CommentKind::OuterDoc
+            ]
+        );
+        assert_eq!(comments[1].span.lines(), 2..=3);
+        let items = RustItemParser::parse_result_items(src, FALLBACK_EDITION).unwrap();
+        let documented = comments[3].attached_to.unwrap();
+        assert_eq!(items[&documented].names.name, "lifetimes");
+    }
+
     const _JSON: &str = r#"{
   "files": [
     {
@@ -151,6 +472,472 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_skeleton() {
+        let src = r#"/// Adds one.
+#[inline]
+pub fn add_one(x: u32) -> u32 {
+    fn inner(x: u32) -> u32 {
+        x + 1
+    }
+    inner(x)
+}
+
+/// A point.
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+pub trait Shape {
+    fn area(&self) -> f64;
+    fn describe(&self) -> String {
+        format!("{}", self.area())
+    }
+}"#;
+        let expected = r#"/// Adds one.
+#[inline]
+pub fn add_one(x: u32) -> u32 { ... }
+
+/// A point.
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+pub trait Shape {
+    fn area(&self) -> f64;
+    fn describe(&self) -> String { ... }
+}"#;
+        assert_eq!(skeleton(src, Edition::CURRENT), expected);
+        //Short callees keep their body, signature types never do
+        let snippet = |kind| ContextSnippet {
+            kind,
+            range: TextRange::default(),
+            text: "fn callee() { work() }".to_string(),
+        };
+        assert_eq!(
+            snippet(SnippetKind::Callee).skeletonize(100, Edition::CURRENT),
+            snippet(SnippetKind::Callee)
+        );
+        assert_eq!(
+            snippet(SnippetKind::Callee)
+                .skeletonize(10, Edition::CURRENT)
+                .text,
+            "fn callee() { ... }"
+        );
+        assert_eq!(
+            snippet(SnippetKind::SignatureType)
+                .skeletonize(100, Edition::CURRENT)
+                .text,
+            "fn callee() { ... }"
+        );
+    }
+
+    #[test]
+    fn test_syntax_context() {
+        let src = r#"struct Config;
+fn helper() {}
+fn unrelated() {}
+impl Config {
+    fn new() -> Config { Config }
+    fn len(&self) -> usize { 0 }
+    fn changed(config: Config) { helper(); my_helper(); Vec::new(); config.len(); }
+}
+"#;
+        let parsed = parse(src);
+        let changed = item_range(&parsed, "changed");
+        let mut context = syntax_context(&parsed, changed)
+            .into_iter()
+            .map(|snippet| (snippet.kind, snippet.text))
+            .collect::<Vec<(SnippetKind, String)>>();
+        context.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(
+            context,
+            vec![
+                (SnippetKind::Callee, "fn helper() {}".to_string()),
+                (
+                    SnippetKind::Callee,
+                    "fn len(&self) -> usize { 0 }".to_string()
+                ),
+                (SnippetKind::SameModule, "struct Config;".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_module_context() {
+        let src = r#"//! Parsing helpers.
+use std::fmt;
+
+mod inner {
+    //! Inner helpers.
+    use super::fmt;
+
+    /// Documented.
+    #[inline]
+    pub fn sibling(a: u32,
+        b: u32) -> u32 {
+        a + b
+    }
+
+    pub struct Holder {
+        value: u32,
+    }
+
+    pub fn changed() {}
+}
+"#;
+        let parsed = parse(src);
+        let changed = item_range(&parsed, "changed");
+        let module_context = parsed.module_context(changed).unwrap();
+        assert_eq!(module_context.path, vec!["inner".to_string()]);
+        assert_eq!(
+            module_context.text,
+            "//! Inner helpers.\nuse super::fmt;\npub fn sibling(a: u32, b: u32) -> u32\npub struct Holder"
+        );
+    }
+
+    #[test]
+    fn test_enclosing_type() {
+        let src = r#"/// A growable buffer.
+///
+/// Backed by a vector.
+pub struct Buffer<T> {
+    items: Vec<T>,
+}
+
+impl<T> Buffer<T> {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+impl<T> Default for Buffer<T> {
+    fn default() -> Self {
+        Buffer { items: vec![] }
+    }
+}
+
+/// Something that can be drawn.
+pub trait Draw<C> {
+    fn draw(&self, canvas: &mut C) {}
+}
+
+pub fn free() {}
+"#;
+        let parsed = parse(src);
+        let enclosing = |name: &str| parsed.enclosing_type(item_range(&parsed, name));
+        let len = enclosing("len").unwrap();
+        assert_eq!(len.class_name, "Buffer<T>");
+        assert_eq!(len.doc.as_deref(), Some("A growable buffer."));
+        assert_eq!(
+            enclosing("default").unwrap().class_name,
+            "Buffer<T> (impl Default)"
+        );
+        let draw = enclosing("draw").unwrap();
+        assert_eq!(draw.class_name, "Draw<C>");
+        assert_eq!(draw.doc.as_deref(), Some("Something that can be drawn."));
+        assert_eq!(enclosing("free"), None);
+    }
+
+    #[test]
+    fn test_render_expansion() {
+        //Expansions have no whitespace, the renderer only looks at the tokens
+        let src = "fn  f ( a:u32 )->u32{let x=g(a,1)?;if x.ok(){}\nfor i in v.iter(){h(&i);}x}";
+        let tree = ra_ap_syntax::SourceFile::parse(src, Edition::CURRENT).tree();
+        assert_eq!(
+            render_expansion(tree.syntax()),
+            "fn f(a: u32) -> u32 {\n    let x = g(a, 1)?;\n    if x.ok() {}\n    for i in v.iter() {\n        h(&i);\n    }\n    x\n}"
+        );
+    }
+
+    #[test]
+    fn test_rank_snippets() {
+        let snippet = |kind, text: &str| ContextSnippet {
+            kind,
+            range: TextRange::default(),
+            text: text.to_string(),
+        };
+        let snippets = vec![
+            snippet(SnippetKind::Test, "fn test_it() {}"),
+            snippet(SnippetKind::Caller, "fn caller() { it() }"),
+            snippet(
+                SnippetKind::Callee,
+                "fn long_callee() { /* a lot of code */ }",
+            ),
+            snippet(SnippetKind::Callee, "fn callee() {}"),
+            snippet(SnippetKind::SignatureType, "struct Input;"),
+            snippet(SnippetKind::SignatureType, "struct Input;"),
+        ];
+        let texts = |snippets: Vec<ContextSnippet>| {
+            snippets
+                .into_iter()
+                .map(|snippet| snippet.text)
+                .collect::<Vec<String>>()
+        };
+        let mut reversed = snippets.clone();
+        reversed.reverse();
+        //Order doesn't depend on the order snippets were gathered in, duplicates are dropped
+        assert_eq!(
+            texts(rank_snippets(snippets.clone(), usize::MAX)),
+            texts(rank_snippets(reversed, usize::MAX))
+        );
+        assert_eq!(
+            texts(rank_snippets(snippets.clone(), usize::MAX)),
+            vec![
+                "fn callee() {}",
+                "fn long_callee() { /* a lot of code */ }",
+                "struct Input;",
+                "fn caller() { it() }",
+                "fn test_it() {}",
+            ]
+        );
+        //The first snippet that doesn't fit ends the context, less relevant ones never take its place
+        assert_eq!(
+            texts(rank_snippets(snippets.clone(), 30)),
+            vec!["fn callee() {}"]
+        );
+        assert_eq!(
+            texts(rank_snippets(snippets.clone(), 67)),
+            vec![
+                "fn callee() {}",
+                "fn long_callee() { /* a lot of code */ }",
+                "struct Input;"
+            ]
+        );
+        //Dependency docs only fill what the workspace's own context leaves, and never keep a body
+        let dependency = snippet(
+            SnippetKind::Dependency,
+            "// crate git2\n/// Creates a diff.\npub fn diff() -> Diff { Diff::new() }",
+        )
+        .skeletonize(usize::MAX, Edition::Edition2021);
+        assert_eq!(
+            dependency.text,
+            "// crate git2\n/// Creates a diff.\npub fn diff() -> Diff { ... }"
+        );
+        let mut with_dependency = snippets;
+        with_dependency.push(dependency.clone());
+        assert_eq!(
+            rank_snippets(with_dependency, usize::MAX).last(),
+            Some(&dependency)
+        );
+    }
+
+    const FIXTURE_LIB: &str = r#"mod shapes;
+#[cfg(test)]
+mod checks;
+
+pub use shapes::{Area, Circle};
+
+macro_rules! double {
+    ($x:expr) => {
+        $x * 2
+    };
+}
+
+/// A square.
+pub struct Square(pub u32);
+
+impl Area for Square {
+    fn area(&self) -> u32 {
+        self.0 * self.0
+    }
+}
+
+pub fn total(shape: &Square) -> u32 {
+    shape.area() + double!(1)
+}
+
+fn user_a() -> u32 { total(&Square(1)) }
+fn user_b() -> u32 { total(&Square(2)) }
+fn user_c() -> u32 { total(&Square(3)) }
+fn user_d() -> u32 { total(&Square(4)) }
+
+#[test]
+fn test_a() { total(&Square(1)); }
+#[test]
+fn test_b() { total(&Square(2)); }
+#[test]
+fn test_c() { total(&Square(3)); }
+#[test]
+fn test_d() { total(&Square(4)); }
+"#;
+
+    const FIXTURE_SHAPES: &str = r#"/// Something with an area.
+pub trait Area {
+    /// Returns the area.
+    fn area(&self) -> u32;
+}
+
+/// A circle.
+pub struct Circle(pub u32);
+
+impl Area for Circle {
+    fn area(&self) -> u32 {
+        3 * self.0 * self.0
+    }
+}
+
+pub struct Hidden;
+"#;
+
+    //Loads a single-crate fixture workspace the way patchdog does, with the sysroot only when a test needs it
+    fn load_fixture(files: &[(&str, &str)], sysroot: bool) -> (PathBuf, AnalyzerData) {
+        let dir = fs::canonicalize(temp_dir()).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"fixture\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        for (path, src) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, src).unwrap();
+        }
+        let cargo_config = CargoConfig {
+            sysroot: sysroot.then_some(RustLibSource::Discover),
+            all_targets: true,
+            set_test: true,
+            no_deps: true,
+            ..Default::default()
+        };
+        let load_config = LoadCargoConfig {
+            load_out_dirs_from_check: false,
+            with_proc_macro_server: ProcMacroServerChoice::None,
+            prefill_caches: false,
+        };
+        let (db, vfs, _proc_macro) =
+            load_workspace_at(&dir, &cargo_config, &load_config, &|_| {}).unwrap();
+        let krates = ra_ap_hir::Crate::all(&db)
+            .into_iter()
+            .filter(|krate| krate.origin(&db).is_local())
+            .map(Into::into)
+            .collect();
+        let mut analyzer_data = AnalyzerData {
+            db,
+            vfs,
+            krates,
+            index: AnalyzerIndex::default(),
+        };
+        analyzer_data.index = AnalyzerIndex::build(&analyzer_data);
+        (dir, analyzer_data)
+    }
+
+    fn shapes_fixture() -> (PathBuf, AnalyzerData) {
+        load_fixture(
+            &[
+                ("src/lib.rs", FIXTURE_LIB),
+                ("src/shapes.rs", FIXTURE_SHAPES),
+                ("src/checks.rs", "fn helper() {}\n"),
+            ],
+            false,
+        )
+    }
+
+    #[test]
+    fn test_method_call_context() {
+        let (dir, analyzer_data) = shapes_fixture();
+        let lib = dir.join("src/lib.rs");
+        let parsed = parse(FIXTURE_LIB);
+        //`shape.area()` resolves to the impl of `Square`, which brings the declaration of the trait along
+        let context = contextualizer(&lib, Some(&item_range(&parsed, "total")), &analyzer_data);
+        let has = |kind: SnippetKind, text: &str| {
+            context
+                .iter()
+                .any(|snippet| snippet.kind == kind && snippet.text.contains(text))
+        };
+        assert!(has(SnippetKind::Callee, "self.0 * self.0"));
+        assert!(has(SnippetKind::Callee, "fn area(&self) -> u32;"));
+        assert!(has(
+            SnippetKind::SignatureType,
+            "pub struct Square(pub u32);"
+        ));
+        //The impl of `Square` is shown with the other implementations of the trait and their contract
+        let area = item_range(&parsed, "area");
+        let context = contextualizer(&lib, Some(&area), &analyzer_data);
+        assert!(context.iter().any(|snippet| {
+            snippet.kind == SnippetKind::SiblingImpl && snippet.text.contains("3 * self.0 * self.0")
+        }));
+        assert!(
+            trait_declaration(&lib, area, &analyzer_data)
+                .unwrap()
+                .contains("fn area(&self) -> u32;")
+        );
+        assert_eq!(
+            type_doc(&lib, area, &analyzer_data).as_deref(),
+            Some("A square.")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_function_usages() {
+        let (dir, analyzer_data) = shapes_fixture();
+        let lib = dir.join("src/lib.rs");
+        let total = item_range(&parse(FIXTURE_LIB), "total");
+        //Four callers and four tests are found, three of each are kept
+        let usages = function_usages(&lib, total, &analyzer_data);
+        let count =
+            |kind: SnippetKind| usages.iter().filter(|snippet| snippet.kind == kind).count();
+        assert_eq!(count(SnippetKind::Caller), 3);
+        assert_eq!(count(SnippetKind::Test), 3);
+        assert!(
+            usages
+                .iter()
+                .all(|snippet| snippet.text.contains("total(&Square("))
+        );
+        //Files included by `#[cfg(test)] mod checks;` are test code, the crate root isn't
+        assert!(is_test_module(&dir.join("src/checks.rs"), &analyzer_data));
+        assert!(!is_test_module(&lib, &analyzer_data));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_externally_reachable() {
+        let (dir, analyzer_data) = shapes_fixture();
+        let reachable = externally_reachable(&analyzer_data);
+        let is_reachable = |file: &str, src: &str, name: &str| {
+            let path = VfsPath::new_real_path(dir.join(file).display().to_string());
+            reachable.contains(&(path, item_range(&parse(src), name)))
+        };
+        //`Circle` lives in a private module but is re-exported, `Hidden` isn't
+        assert!(is_reachable("src/shapes.rs", FIXTURE_SHAPES, "Circle"));
+        assert!(!is_reachable("src/shapes.rs", FIXTURE_SHAPES, "Hidden"));
+        assert!(is_reachable("src/lib.rs", FIXTURE_LIB, "total"));
+        assert!(!is_reachable("src/lib.rs", FIXTURE_LIB, "user_a"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_macro_expansions() {
+        let (dir, analyzer_data) = shapes_fixture();
+        let lib = dir.join("src/lib.rs");
+        let total = item_range(&parse(FIXTURE_LIB), "total");
+        assert_eq!(
+            macro_expansions(&lib, total, &analyzer_data, usize::MAX),
+            vec!["// double! expands to\n1 * 2".to_string()]
+        );
+        assert!(macro_expansions(&lib, total, &analyzer_data, 0).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dependency_context() {
+        let src = "pub fn exchange(a: &mut u32, b: &mut u32) {\n    core::mem::swap(a, b)\n}\n";
+        let (dir, analyzer_data) = load_fixture(&[("src/lib.rs", src)], true);
+        let lib = dir.join("src/lib.rs");
+        let exchange = item_range(&parse(src), "exchange");
+        let context = dependency_context(&lib, exchange, &analyzer_data, usize::MAX);
+        assert!(context.iter().any(|snippet| {
+            snippet.kind == SnippetKind::Dependency
+                && snippet.text.starts_with("// crate core\n")
+                && snippet.text.contains("fn swap")
+        }));
+        assert!(dependency_context(&lib, exchange, &analyzer_data, 0).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
     #[test]
     fn test_match() {
         let names = Name {
@@ -158,7 +945,7 @@ mod tests {
             name: "request_manager".to_string(),
         };
         let _input = ObjectRange {
-            line_ranges: 395..429,
+            span: Span::default(),
             names,
         };
 
@@ -0,0 +1,52 @@
+use ra_ap_syntax::{AstNode, SyntaxNode, ast};
+
+/// Attribute paths that mark a function as a test or a benchmark. Any other path ending in `::test`,
+/// such as `tokio::test` or `async_std::test`, is treated as a test attribute as well.
+const TEST_ATTRIBUTES: [&str; 3] = ["test", "bench", "test_case"];
+
+/// Tells whether the attributes of `node` mark it as test code: `#[test]`, `#[bench]` or a runtime specific
+/// test attribute on a function, or `#[cfg(test)]` on any item. For a source file root, its inner attributes
+/// are checked, so a file starting with `#![cfg(test)]` is test code as a whole.
+///
+/// # Arguments
+///
+/// * `node` - The syntax node of an item, or the root of a source file.
+///
+/// # Returns
+///
+/// `true` if the node is only compiled for tests or is itself a test or a benchmark.
+pub fn is_test_code(node: &SyntaxNode) -> bool {
+    node.children()
+        .filter_map(ast::Attr::cast)
+        .any(|attr| is_test_attribute(&attr))
+}
+
+fn is_test_attribute(attr: &ast::Attr) -> bool {
+    let Some(path) = attr.path().map(|path| path.syntax().text().to_string()) else {
+        return false;
+    };
+    if path == "cfg" {
+        let predicate = attr
+            .token_tree()
+            .map(|tt| tt.syntax().text().to_string())
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<String>();
+        return cfg_requires_test(&predicate);
+    }
+    TEST_ATTRIBUTES.contains(&path.as_str()) || path.ends_with("::test")
+}
+
+/// `cfg(test)` and `cfg(all(test, ...))` only compile with the test configuration. `cfg(any(test, ...))` and
+/// `cfg(not(test))` may also compile without it, so they are not treated as test code.
+fn cfg_requires_test(predicate: &str) -> bool {
+    if predicate == "(test)" {
+        return true;
+    }
+    let Some(all) = predicate.strip_prefix("(all(") else {
+        return false;
+    };
+    all.trim_end_matches(')')
+        .split(',')
+        .any(|clause| clause == "test")
+}
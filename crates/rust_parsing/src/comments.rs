@@ -0,0 +1,164 @@
+use crate::object_range::{Name, ObjectRange};
+use crate::span::Span;
+use ra_ap_ide::TextRange;
+use ra_ap_syntax::ast::{self, AstToken};
+use ra_ap_syntax::{AstNode, NodeOrToken, SyntaxKind, SyntaxNode, SyntaxToken};
+use serde::{Deserialize, Serialize};
+
+/// Classification of a comment token, following rustdoc's notion of doc comments.
+/// `/** */` block doc comments are classified together with `///`, as rustdoc treats them the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CommentKind {
+    OuterDoc,
+    InnerDoc,
+    Line,
+    Block,
+}
+
+impl CommentKind {
+    /// Returns the name under which this kind of comment is reported in `ObjectRange::names.type_name`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            CommentKind::OuterDoc => "OuterDocComment",
+            CommentKind::InnerDoc => "InnerDocComment",
+            CommentKind::Line => "LineComment",
+            CommentKind::Block => "BlockComment",
+        }
+    }
+
+    pub fn is_doc(&self) -> bool {
+        matches!(self, CommentKind::OuterDoc | CommentKind::InnerDoc)
+    }
+}
+
+/// A single comment found in the syntax tree.
+/// `span` locates the comment token itself, its `lines()` are 1-based and inclusive like every other `Span`.
+/// `attached_to` holds the `TextRange` of the item the comment documents: the item it directly precedes for outer
+/// and plain comments, or the enclosing item for inner doc comments. `None` means the comment is free-standing,
+/// or is an inner doc comment of the file itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CommentRange {
+    pub kind: CommentKind,
+    pub span: Span,
+    pub text: String,
+    pub attached_to: Option<TextRange>,
+}
+
+impl CommentRange {
+    /// Represents the comment as an `ObjectRange`, so it can be merged with the rest of the parsed items.
+    pub fn to_object_range(&self) -> ObjectRange {
+        ObjectRange {
+            span: self.span.clone(),
+            names: Name {
+                type_name: self.kind.type_name().to_string(),
+                name: "Comment".to_string(),
+            },
+        }
+    }
+}
+
+/// Walks every token of the parsed file and collects all comments, classifying each one and attaching it to the item it belongs to.
+/// Comments are taken straight from the `rust-analyzer` trivia, so string literals containing `//`, lifetimes and block comments spanning several lines are handled the same way the compiler sees them.
+///
+/// # Arguments
+///
+/// * `root` - The root `SyntaxNode` of a parsed source file.
+/// * `to_span` - Converts a byte `TextRange` into a `Span` of the parsed file.
+///
+/// # Returns
+///
+/// A `Vec<CommentRange>` ordered by position in the file.
+pub fn collect_comments(
+    root: &SyntaxNode,
+    to_span: impl Fn(TextRange) -> Span,
+) -> Vec<CommentRange> {
+    root.descendants_with_tokens()
+        .filter_map(|element| element.into_token())
+        .filter_map(ast::Comment::cast)
+        .map(|comment| {
+            let kind = classify(&comment);
+            let token = comment.syntax();
+            let attached_to = if kind == CommentKind::InnerDoc {
+                enclosing_item(token)
+            } else {
+                following_item(token)
+            }
+            .map(|item| item.syntax().text_range());
+            CommentRange {
+                kind,
+                span: to_span(token.text_range()),
+                text: comment.text().to_string(),
+                attached_to,
+            }
+        })
+        .collect()
+}
+
+/// Returns the doc comments attached to the item at `item_range`, with comment markers stripped, in source order.
+pub fn item_docs(comments: &[CommentRange], item_range: TextRange) -> Vec<String> {
+    comments
+        .iter()
+        .filter(|comment| comment.kind.is_doc() && comment.attached_to == Some(item_range))
+        .map(|comment| strip_comment_markers(&comment.text))
+        .collect()
+}
+
+fn classify(comment: &ast::Comment) -> CommentKind {
+    let kind = comment.kind();
+    match (kind.doc, kind.shape) {
+        (Some(ast::CommentPlacement::Outer), _) => CommentKind::OuterDoc,
+        (Some(ast::CommentPlacement::Inner), _) => CommentKind::InnerDoc,
+        (None, ast::CommentShape::Line) => CommentKind::Line,
+        (None, ast::CommentShape::Block) => CommentKind::Block,
+    }
+}
+
+fn strip_comment_markers(text: &str) -> String {
+    let text = text.trim();
+    if let Some(block) = text
+        .strip_prefix("/**")
+        .or_else(|| text.strip_prefix("/*!"))
+    {
+        return block.trim_end_matches("*/").trim().to_string();
+    }
+    text.trim_start_matches("///")
+        .trim_start_matches("//!")
+        .trim()
+        .to_string()
+}
+
+fn enclosing_item(token: &SyntaxToken) -> Option<ast::Item> {
+    token.parent_ancestors().find_map(ast::Item::cast)
+}
+
+fn following_item(token: &SyntaxToken) -> Option<ast::Item> {
+    // rust-analyzer moves trivia that directly precedes an item inside of the item node,
+    // so the comment is attached when only trivia and attributes come before it in its parent
+    if let Some(parent) = token.parent()
+        && let Some(item) = ast::Item::cast(parent.clone())
+    {
+        let leading = parent
+            .children_with_tokens()
+            .take_while(|element| element.kind().is_trivia() || element.kind() == SyntaxKind::ATTR)
+            .any(|element| element.as_token() == Some(token));
+        if leading {
+            return Some(item);
+        }
+    }
+    // Otherwise the next sibling is checked, a blank line breaks the attachment
+    let mut next = token.next_sibling_or_token();
+    while let Some(element) = next {
+        next = element.next_sibling_or_token();
+        match element {
+            NodeOrToken::Token(t) if t.kind() == SyntaxKind::WHITESPACE => {
+                if t.text().matches('\n').count() > 1 {
+                    return None;
+                }
+            }
+            NodeOrToken::Token(t) if t.kind() == SyntaxKind::COMMENT => {}
+            NodeOrToken::Node(n) => return ast::Item::cast(n),
+            NodeOrToken::Token(_) => return None,
+        }
+    }
+    None
+}
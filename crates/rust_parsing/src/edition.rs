@@ -0,0 +1,71 @@
+use ra_ap_ide::Edition;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Edition that is used when a file does not belong to any package, e.g. loose files outside of a crate.
+pub const FALLBACK_EDITION: Edition = Edition::Edition2024;
+
+/// Resolves the edition a Rust file is compiled with, by reading the manifest of the package that owns it.
+/// The closest `Cargo.toml` with a `[package]` table is used. `edition.workspace = true` is followed to the
+/// `[workspace.package]` table of the enclosing workspace manifest, and a package without an `edition` key gets
+/// Cargo's default, `2015`.
+///
+/// # Arguments
+///
+/// * `file` - Path to the Rust source file.
+///
+/// # Returns
+///
+/// The `Edition` of the owning package, or `FALLBACK_EDITION` if no owning package could be found.
+pub fn edition_for_file(file: &Path) -> Edition {
+    let Some((manifest_path, package)) = owning_package(file) else {
+        return FALLBACK_EDITION;
+    };
+    match package.get("edition") {
+        Some(Value::String(edition)) => parse_edition(edition).unwrap_or(FALLBACK_EDITION),
+        Some(Value::Table(inherited))
+            if inherited.get("workspace").and_then(Value::as_bool) == Some(true) =>
+        {
+            workspace_edition(&manifest_path).unwrap_or(FALLBACK_EDITION)
+        }
+        _ => Edition::Edition2015,
+    }
+}
+
+fn parse_edition(edition: &str) -> Option<Edition> {
+    match edition {
+        "2015" => Some(Edition::Edition2015),
+        "2018" => Some(Edition::Edition2018),
+        "2021" => Some(Edition::Edition2021),
+        "2024" => Some(Edition::Edition2024),
+        _ => None,
+    }
+}
+
+fn read_manifest(path: &Path) -> Option<Table> {
+    fs::read_to_string(path).ok()?.parse::<Table>().ok()
+}
+
+fn owning_package(file: &Path) -> Option<(PathBuf, Table)> {
+    file.ancestors().skip(1).find_map(|dir| {
+        let manifest_path = dir.join("Cargo.toml");
+        let mut manifest = read_manifest(&manifest_path)?;
+        match manifest.remove("package") {
+            Some(Value::Table(package)) => Some((manifest_path, package)),
+            _ => None,
+        }
+    })
+}
+
+fn workspace_edition(member_manifest: &Path) -> Option<Edition> {
+    member_manifest.ancestors().skip(1).find_map(|dir| {
+        let manifest = read_manifest(&dir.join("Cargo.toml"))?;
+        let edition = manifest
+            .get("workspace")?
+            .get("package")?
+            .get("edition")?
+            .as_str()?;
+        parse_edition(edition)
+    })
+}
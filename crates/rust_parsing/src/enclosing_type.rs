@@ -0,0 +1,108 @@
+use ra_ap_syntax::ast::{self, HasName};
+use ra_ap_syntax::{AstNode, SyntaxKind, SyntaxNode, TextRange};
+
+/// The type or trait a method is declared in, so the LLM knows what `self` represents.
+/// `class_name` is the self type of the impl with its generics, followed by the implemented trait if any,
+/// or the name of the trait for its default methods. `doc` is the summary of the type's own documentation
+/// when the type is declared in the same file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnclosingType {
+    pub class_name: String,
+    pub doc: Option<String>,
+}
+
+impl EnclosingType {
+    /// Finds the `impl` or `trait` block directly containing the item whose syntax node spans exactly `range`.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The root of the syntax tree of the file.
+    /// * `range` - The `TextRange` of the item.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<EnclosingType>`, `None` for items that aren't associated items.
+    pub fn of_item(root: &SyntaxNode, range: TextRange) -> Option<EnclosingType> {
+        let item = root
+            .descendants()
+            .filter_map(ast::AssocItem::cast)
+            .find(|item| item.syntax().text_range() == range)?;
+        let container = item.syntax().parent()?.parent()?;
+        if let Some(impl_) = ast::Impl::cast(container.clone()) {
+            let self_ty = impl_.self_ty()?;
+            let class_name = match impl_.trait_() {
+                Some(trait_) => format!("{self_ty} (impl {trait_})"),
+                None => self_ty.to_string(),
+            };
+            return Some(EnclosingType {
+                class_name,
+                doc: type_name(&self_ty).and_then(|name| type_doc(root, &name)),
+            });
+        }
+        let trait_ = ast::Trait::cast(container)?;
+        let name = trait_.name()?.to_string();
+        Some(EnclosingType {
+            class_name: match trait_.generic_param_list() {
+                Some(generics) => format!("{name}{generics}"),
+                None => name,
+            },
+            doc: doc_summary(trait_.syntax()),
+        })
+    }
+}
+
+/// Returns the first paragraph of a documentation text, joined on a single line.
+pub fn summary(doc: &str) -> Option<String> {
+    let summary = doc
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| line.is_empty())
+        .take_while(|line| !line.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    (!summary.is_empty()).then_some(summary)
+}
+
+/// Returns the summary of the `///` and `/** */` comments of an item node.
+fn doc_summary(node: &SyntaxNode) -> Option<String> {
+    let doc = node
+        .children_with_tokens()
+        .filter_map(|element| element.into_token())
+        .filter(|token| token.kind() == SyntaxKind::COMMENT)
+        .filter_map(|token| {
+            let text = token.text();
+            text.strip_prefix("///")
+                .or_else(|| {
+                    text.strip_prefix("/**")
+                        .and_then(|doc| doc.strip_suffix("*/"))
+                })
+                .map(str::to_string)
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    summary(&doc)
+}
+
+/// Returns the name the self type of an impl is declared with, `Foo` for `Foo<T>` or `module::Foo`.
+fn type_name(self_ty: &ast::Type) -> Option<String> {
+    match self_ty {
+        ast::Type::PathType(path_type) => path_type
+            .path()?
+            .segment()?
+            .name_ref()
+            .map(|name| name.to_string()),
+        _ => None,
+    }
+}
+
+/// Finds the struct, enum, union or trait named `name` in the file and returns its doc summary.
+fn type_doc(root: &SyntaxNode, name: &str) -> Option<String> {
+    root.descendants()
+        .find(|node| {
+            ast::Adt::cast(node.clone())
+                .and_then(|adt| adt.name())
+                .or_else(|| ast::Trait::cast(node.clone()).and_then(|trait_| trait_.name()))
+                .is_some_and(|adt_name| adt_name.to_string() == name)
+        })
+        .and_then(|node| doc_summary(&node))
+}
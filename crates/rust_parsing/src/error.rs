@@ -84,6 +84,28 @@ pub enum ErrorHandling {
     InvalidRead {
         source: std::io::Error,
     },
+    #[snafu(display("Semantic analysis unavailable, {stage} failed: {source}"))]
+    AnalyzerInit {
+        stage: String,
+        source: anyhow::Error,
+    },
+    #[snafu(display("Semantic analysis unavailable, the workspace didn't load within {seconds}s"))]
+    AnalyzerTimeout {
+        seconds: usize,
+    },
+    #[snafu(display("Semantic analysis unavailable, loading panicked: {message}"))]
+    AnalyzerPanic {
+        message: String,
+    },
+    #[snafu(display("Setting {key} of the configuration must not be negative, got {value}"))]
+    InvalidSetting {
+        key: String,
+        value: i64,
+    },
+    #[snafu(display("Package {name} isn't a local package of the workspace"))]
+    UnknownPackage {
+        name: String,
+    },
 }
 
 #[derive(Debug)]
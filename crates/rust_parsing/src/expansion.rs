@@ -0,0 +1,79 @@
+use ra_ap_syntax::{SyntaxKind, SyntaxNode};
+
+/// Renders the syntax tree of a macro expansion as readable source. Expansions carry no whitespace of their own, so
+/// their tokens are joined by single spaces, except around punctuation that is written attached, and every statement,
+/// block and item starts on its own line, indented by four spaces per enclosing brace.
+///
+/// # Arguments
+///
+/// * `expansion` - The root of the expanded syntax tree.
+///
+/// # Returns
+///
+/// A `String` with the expanded code, empty if the expansion has no tokens.
+pub fn render_expansion(expansion: &SyntaxNode) -> String {
+    let mut text = String::new();
+    let mut depth = 0usize;
+    let mut previous: Option<SyntaxKind> = None;
+    for token in expansion
+        .descendants_with_tokens()
+        .filter_map(|element| element.into_token())
+        .filter(|token| !token.kind().is_trivia())
+    {
+        let kind = token.kind();
+        if kind == SyntaxKind::R_CURLY {
+            depth = depth.saturating_sub(1);
+        }
+        if let Some(previous) = previous {
+            let ends_line = matches!(
+                previous,
+                SyntaxKind::SEMICOLON | SyntaxKind::L_CURLY | SyntaxKind::R_CURLY
+            ) && !matches!(
+                kind,
+                SyntaxKind::COMMA | SyntaxKind::SEMICOLON | SyntaxKind::R_PAREN | SyntaxKind::DOT
+            );
+            if previous == SyntaxKind::L_CURLY && kind == SyntaxKind::R_CURLY {
+                //Empty blocks stay on one line
+            } else if ends_line || kind == SyntaxKind::R_CURLY {
+                text.push('\n');
+                text.push_str(&"    ".repeat(depth));
+            } else if !attached(previous, kind) {
+                text.push(' ');
+            }
+        }
+        if kind == SyntaxKind::L_CURLY {
+            depth += 1;
+        }
+        text.push_str(token.text());
+        previous = Some(kind);
+    }
+    text
+}
+
+/// Tells whether two consecutive tokens are written without a space between them, as in `foo(a, b)`, `x.y?` or `a::b`.
+fn attached(previous: SyntaxKind, next: SyntaxKind) -> bool {
+    matches!(
+        next,
+        SyntaxKind::COMMA
+            | SyntaxKind::SEMICOLON
+            | SyntaxKind::DOT
+            | SyntaxKind::R_PAREN
+            | SyntaxKind::R_BRACK
+            | SyntaxKind::QUESTION
+            | SyntaxKind::COLON2
+            | SyntaxKind::COLON
+    ) || matches!(
+        previous,
+        SyntaxKind::L_PAREN
+            | SyntaxKind::L_BRACK
+            | SyntaxKind::DOT
+            | SyntaxKind::COLON2
+            | SyntaxKind::POUND
+            | SyntaxKind::AMP
+            | SyntaxKind::BANG
+    ) || (matches!(next, SyntaxKind::L_PAREN | SyntaxKind::L_BRACK)
+        && matches!(
+            previous,
+            SyntaxKind::IDENT | SyntaxKind::SELF_KW | SyntaxKind::R_ANGLE
+        ))
+}
@@ -1,14 +1,12 @@
 use crate::error::{ErrorHandling, InvalidIoOperationsSnafu};
 use crate::object_range::ObjectRange;
+use crate::span::Span;
 use snafu::ResultExt;
 use std::{fs::File, io::Write, path::PathBuf};
 pub const REGEX: &str = r#"\{\s*"uuid"\s*:\s*"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}",\s*"new_comment"\s*:\s*".*"\s*\}"#;
 pub struct FileExtractor;
 pub trait Files {
-    fn check_for_valid_object(
-        parsed: &[ObjectRange],
-        line_number: usize,
-    ) -> Result<bool, ErrorHandling>;
+    fn check_for_valid_object(parsed: &[ObjectRange], span: &Span) -> Result<bool, ErrorHandling>;
 
     fn string_to_vector(source: &str) -> Vec<String>;
 
@@ -103,23 +101,20 @@ impl Files for FileExtractor {
         Ok(source_clone)
     }
 
-    /// Checks if a given `line_number` falls within any of the provided `ObjectRange` items. This utility function helps determine if a specific line of code is part of a recognized structural element (like a function or struct) within the parsed code.
-    /// It iterates through a slice of `ObjectRange` objects and returns `false` as soon as it finds a range that contains the `line_number`, indicating that the line is validly part of an object. If no such object is found, it returns `true`.
+    /// Checks if a given `span` falls within any of the provided `ObjectRange` items. This utility function helps determine if a specific line of code is part of a recognized structural element (like a function or struct) within the parsed code.
+    /// It iterates through a slice of `ObjectRange` objects and returns `false` as soon as it finds a range that overlaps the `span`, indicating that the line is validly part of an object. If no such object is found, it returns `true`.
     ///
     /// # Arguments
     ///
     /// * `parsed` - A slice of `ObjectRange` representing the parsed code objects and their line ranges.
-    /// * `line_number` - The `usize` line number to check.
+    /// * `span` - The `Span` to check, such as the line of a patch hunk.
     ///
     /// # Returns
     ///
     /// A `Result<bool, ErrorHandling>` which is `false` if the line number is within an object's range, `true` otherwise, or an `ErrorHandling` if an internal error occurs.
-    fn check_for_valid_object(
-        parsed: &[ObjectRange],
-        line_number: usize,
-    ) -> Result<bool, ErrorHandling> {
+    fn check_for_valid_object(parsed: &[ObjectRange], span: &Span) -> Result<bool, ErrorHandling> {
         for each in parsed {
-            if each.line_ranges.start <= line_number && line_number <= each.line_ranges.end {
+            if each.span.overlaps(span) {
                 return Ok(false);
             }
         }
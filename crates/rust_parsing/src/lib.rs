@@ -7,12 +7,29 @@
 //! Syn crate itself provides functionality to pull out objects from a file, albeit it loses very helpful //comments, so instead
 //! it was chosen as best practice to only get line numbers and from there pull out the whole object.
 //! Error handling is carried out with SNAFU.
+pub mod attributes;
+pub mod comments;
+pub mod edition;
+pub mod enclosing_type;
 pub mod error;
+pub mod expansion;
 pub mod file_parsing;
+pub mod macro_definition;
+pub mod module_context;
 pub mod object_range;
+pub mod parsed_file;
 pub mod rust_parser;
+pub mod signature;
+pub mod skeleton;
+pub mod span;
+pub mod visibility;
 
+pub use comments::{CommentKind, CommentRange};
+pub use edition::edition_for_file;
 pub use error::ErrorHandling;
 pub use object_range::ObjectRange;
+pub use parsed_file::{ParseCache, ParsedFile, SyntaxErrorRange};
 pub use rust_parser::comment_lexer;
 pub use rust_parser::remove_whitespace;
+pub use span::{FileId, LineCol, LineIndex, Span, SpanExt};
+pub use visibility::Visibility;
@@ -0,0 +1,98 @@
+use ra_ap_syntax::ast::{self, HasName};
+use ra_ap_syntax::{AstNode, SyntaxKind, SyntaxNode, SyntaxToken};
+use serde::{Deserialize, Serialize};
+
+/// Structured description of a `macro_rules!` definition, sent to the LLM next to the macro text.
+/// Every arm is listed with its matcher and the fragment specifiers it binds, so the generated documentation can
+/// describe each accepted form of the macro. `usages` holds invocations of the macro found elsewhere in the workspace.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MacroDefinition {
+    pub name: String,
+    pub arms: Vec<MacroArm>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub usages: Vec<String>,
+}
+
+/// A single `(matcher) => { transcriber }` rule of a macro.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MacroArm {
+    pub matcher: String,
+    pub fragments: Vec<MacroFragment>,
+}
+
+/// A metavariable bound by a matcher, e.g. `$name:ident` has the name `name` and the kind `ident`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MacroFragment {
+    pub name: String,
+    pub kind: String,
+}
+
+impl MacroDefinition {
+    /// Builds the description of a `macro_rules!` definition from its syntax node. The rules are read from the token tree
+    /// of the macro body, where matchers and transcribers alternate as delimited token trees separated by `=>` and `;`.
+    ///
+    /// # Arguments
+    ///
+    /// * `macro_rules` - The `ast::MacroRules` node to describe.
+    ///
+    /// # Returns
+    ///
+    /// A `MacroDefinition` with one `MacroArm` per rule and no usages.
+    pub fn from_macro_rules(macro_rules: &ast::MacroRules) -> MacroDefinition {
+        let arms = macro_rules
+            .token_tree()
+            .map(|body| {
+                body.syntax()
+                    .children()
+                    .filter(|tree| tree.kind() == SyntaxKind::TOKEN_TREE)
+                    .step_by(2)
+                    .map(|matcher| MacroArm {
+                        matcher: matcher.text().to_string(),
+                        fragments: fragments(&matcher),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        MacroDefinition {
+            name: macro_rules
+                .name()
+                .map(|name| name.to_string())
+                .unwrap_or_default(),
+            arms,
+            usages: vec![],
+        }
+    }
+}
+
+/// Collects the `$name:kind` metavariables of a matcher, including the ones inside `$(...)` repetitions.
+fn fragments(matcher: &SyntaxNode) -> Vec<MacroFragment> {
+    let tokens = matcher
+        .descendants_with_tokens()
+        .filter_map(|element| element.into_token())
+        .filter(|token| !token.kind().is_trivia())
+        .collect::<Vec<SyntaxToken>>();
+    tokens
+        .windows(4)
+        .filter_map(|window| match window {
+            [dollar, name, colon, kind]
+                if dollar.kind() == SyntaxKind::DOLLAR
+                    && colon.kind() == SyntaxKind::COLON
+                    && is_word(name)
+                    && is_word(kind) =>
+            {
+                Some(MacroFragment {
+                    name: name.text().to_string(),
+                    kind: kind.text().to_string(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_word(token: &SyntaxToken) -> bool {
+    token
+        .text()
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_')
+}
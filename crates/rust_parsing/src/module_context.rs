@@ -0,0 +1,110 @@
+use ra_ap_syntax::ast::{self, HasName};
+use ra_ap_syntax::{AstNode, SyntaxKind, SyntaxNode, TextRange};
+
+/// Compact description of the module an item is declared in: its `//!` docs, its `use` items and the signatures of the
+/// other items declared next to it. `path` holds the names of the inline modules leading to it, empty for the file itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleContext {
+    pub path: Vec<String>,
+    pub text: String,
+}
+
+impl ModuleContext {
+    /// Describes the module enclosing the item whose syntax node spans exactly `range`. The enclosing module is the closest
+    /// inline `mod` around the item, or the file when there is none. Items nested in an `impl` or a `trait` belong to the
+    /// module of that block.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The root of the syntax tree of the file.
+    /// * `range` - The `TextRange` of the item.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<ModuleContext>`, `None` if no item spans `range` or its module has no docs, imports or other items.
+    pub fn of_item(root: &SyntaxNode, range: TextRange) -> Option<ModuleContext> {
+        let item = root
+            .descendants()
+            .filter(|node| {
+                ast::Item::can_cast(node.kind()) || ast::AssocItem::can_cast(node.kind())
+            })
+            .find(|node| node.text_range() == range)?;
+        let module = item.ancestors().skip(1).find_map(ast::Module::cast);
+        let mut path = item
+            .ancestors()
+            .skip(1)
+            .filter_map(ast::Module::cast)
+            .filter_map(|module| module.name().map(|name| name.to_string()))
+            .collect::<Vec<String>>();
+        path.reverse();
+        let (body, items) = match &module {
+            Some(module) => {
+                let item_list = module.item_list()?;
+                (
+                    item_list.syntax().clone(),
+                    item_list.items().collect::<Vec<ast::Item>>(),
+                )
+            }
+            None => {
+                let file = ast::SourceFile::cast(root.clone())?;
+                (root.clone(), file.items().collect::<Vec<ast::Item>>())
+            }
+        };
+        let mut lines = body
+            .children_with_tokens()
+            .filter_map(|element| element.into_token())
+            .filter(|token| token.kind() == SyntaxKind::COMMENT)
+            .map(|token| token.text().to_string())
+            .filter(|comment| comment.starts_with("//!") || comment.starts_with("/*!"))
+            .collect::<Vec<String>>();
+        let (imports, siblings): (Vec<ast::Item>, Vec<ast::Item>) = items
+            .into_iter()
+            .filter(|sibling| !sibling.syntax().text_range().contains_range(range))
+            .partition(|sibling| matches!(sibling, ast::Item::Use(_)));
+        lines.extend(imports.iter().map(|import| header(import.syntax())));
+        lines.extend(siblings.iter().map(|sibling| header(sibling.syntax())));
+        if lines.is_empty() {
+            return None;
+        }
+        Some(ModuleContext {
+            path,
+            text: lines.join("\n"),
+        })
+    }
+}
+
+/// Returns the declaration of an item on a single line, without its attributes, doc comments and body:
+/// the signature of a function, or the first line of a struct, enum, trait or impl.
+fn header(item: &SyntaxNode) -> String {
+    let start = item
+        .children_with_tokens()
+        .find(|element| {
+            !matches!(
+                element.kind(),
+                SyntaxKind::ATTR | SyntaxKind::COMMENT | SyntaxKind::WHITESPACE
+            )
+        })
+        .map_or(item.text_range().start(), |element| {
+            element.text_range().start()
+        });
+    let end = item
+        .children()
+        .find(|child| {
+            matches!(
+                child.kind(),
+                SyntaxKind::BLOCK_EXPR
+                    | SyntaxKind::RECORD_FIELD_LIST
+                    | SyntaxKind::VARIANT_LIST
+                    | SyntaxKind::ASSOC_ITEM_LIST
+                    | SyntaxKind::ITEM_LIST
+                    | SyntaxKind::TOKEN_TREE
+            )
+        })
+        .map_or(item.text_range().end(), |body| body.text_range().start());
+    let offset = item.text_range().start();
+    let text = item.text().to_string();
+    text[usize::from(start - offset)..usize::from(end - offset)]
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
@@ -1,5 +1,6 @@
-use std::ops::Range;
+use std::ops::RangeInclusive;
 
+use crate::span::Span;
 use serde::Serialize;
 
 #[derive(Debug, Clone, serde::Deserialize, Serialize, PartialEq, Hash, Eq)]
@@ -10,7 +11,7 @@ pub struct Name {
 #[derive(Debug, Clone, serde::Deserialize, Serialize, PartialEq, Hash, Eq)]
 pub struct ObjectRange {
     //There is an ample interface for interaction with this structure, hence, I believe there is no reason to change it
-    pub line_ranges: Range<usize>, // Has to stay, as a lot of functionality is bound to this field
+    pub span: Span,
     pub names: Name,
 }
 /*
@@ -24,7 +25,7 @@ line_end = %object%.line_end()
 impl Default for ObjectRange {
     fn default() -> Self {
         Self {
-            line_ranges: 0..0,
+            span: Span::default(),
             names: Name {
                 type_name: "".to_string(),
                 name: "".to_string(),
@@ -60,16 +61,25 @@ impl ObjectRange {
     ///
     /// A `usize` representing the start line number of the object's code block.
     pub fn line_start(&self) -> usize {
-        self.line_ranges.start
+        self.span.start.line
     }
 
-    /// Returns the ending line number of the code object's range. This method provides a simple way to get the upper bound (exclusive) of the line range that the `ObjectRange` instance encompasses.
+    /// Returns the ending line number of the code object's range. This method provides a simple way to get the upper bound (inclusive) of the line range that the `ObjectRange` instance encompasses.
     /// It is often used in conjunction with `line_start` to define the full extent of a code item within a file.
     ///
     /// # Returns
     ///
     /// A `usize` representing the end line number of the object's code block.
     pub fn line_end(&self) -> usize {
-        self.line_ranges.end
+        self.span.end.line
+    }
+
+    /// Returns the 1-based, inclusive range of lines the code object occupies.
+    ///
+    /// # Returns
+    ///
+    /// A `RangeInclusive<usize>` going from `line_start` to `line_end`.
+    pub fn line_ranges(&self) -> RangeInclusive<usize> {
+        self.span.lines()
     }
 }
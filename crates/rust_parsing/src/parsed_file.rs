@@ -0,0 +1,269 @@
+use crate::attributes::is_test_code;
+use crate::comments::{CommentRange, collect_comments};
+use crate::edition::edition_for_file;
+use crate::enclosing_type::EnclosingType;
+use crate::error::{ErrorHandling, InvalidIoOperationsSnafu};
+use crate::macro_definition::MacroDefinition;
+use crate::module_context::ModuleContext;
+use crate::object_range::ObjectRange;
+use crate::rust_parser::{AnalyzerRange, parse_all_rust_analyzer};
+use crate::signature::FunctionSignature;
+use crate::span::{FileId, LineIndex, Span, SpanExt};
+use ra_ap_ide::{Edition, TextRange};
+use ra_ap_syntax::{AstNode, Parse, SourceFile, ast};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// A Rust file that has been read and parsed once, together with everything derived from the syntax tree.
+/// Every stage of a run works on the same `ParsedFile`, so the file is never parsed twice.
+#[derive(Debug)]
+pub struct ParsedFile {
+    pub file_id: FileId,
+    pub path: PathBuf,
+    pub hash: u64,
+    pub edition: Edition,
+    pub source: String,
+    pub line_index: LineIndex,
+    pub parse: Parse<SourceFile>,
+    pub items: HashMap<TextRange, AnalyzerRange>,
+    pub comments: Vec<CommentRange>,
+    pub syntax_errors: Vec<SyntaxErrorRange>,
+}
+
+/// A syntax error reported by the parser, together with the part of the file it covers.
+/// The syntax tree is still recovered around it, but item ranges overlapping it can't be trusted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxErrorRange {
+    pub span: Span,
+    pub message: String,
+}
+
+impl ParsedFile {
+    /// Parses `source` with `rust-analyzer` and derives the line index, the item tree and the comments from the single resulting syntax tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_id` - The `FileId` every `Span` of the file is tagged with.
+    /// * `path` - Path the source was read from.
+    /// * `source` - Contents of the file.
+    /// * `edition` - The `Edition` of the package owning the file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<ParsedFile, ErrorHandling>` holding the parsed file, or an `ErrorHandling` if the item tree could not be built.
+    pub fn new(
+        file_id: FileId,
+        path: &Path,
+        source: String,
+        edition: Edition,
+    ) -> Result<ParsedFile, ErrorHandling> {
+        let hash = content_hash(&source);
+        let line_index = LineIndex::new(&source);
+        let parse = SourceFile::parse(&source, edition);
+        let tree = parse.tree();
+        let items = parse_all_rust_analyzer(tree.items().collect(), is_test_code(tree.syntax()))?;
+        let comments = collect_comments(tree.syntax(), |range| line_index.span(file_id, range));
+        let syntax_errors = parse
+            .errors()
+            .into_iter()
+            .map(|error| SyntaxErrorRange {
+                span: line_index.span(file_id, error.range()),
+                message: error.to_string(),
+            })
+            .collect();
+        Ok(ParsedFile {
+            file_id,
+            path: path.to_path_buf(),
+            hash,
+            edition,
+            source,
+            line_index,
+            parse,
+            items,
+            comments,
+            syntax_errors,
+        })
+    }
+
+    /// Converts a byte `TextRange` of this file into a `Span`, using the precomputed line index.
+    pub fn span(&self, range: TextRange) -> Span {
+        self.line_index.span(self.file_id, range)
+    }
+
+    /// Returns all parsed items of the file with their spans.
+    pub fn object_ranges(&self) -> Vec<ObjectRange> {
+        self.items
+            .values()
+            .map(|item| ObjectRange {
+                span: self.span(item.range),
+                names: item.names.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the parsed items merged with the comments of the file, sorted by their starting line.
+    pub fn all_objects(&self) -> Vec<ObjectRange> {
+        let mut visited = self.object_ranges();
+        visited.extend(self.comments.iter().map(CommentRange::to_object_range));
+        visited.sort_by(|a, b| a.span.start.cmp(&b.span.start));
+        visited
+    }
+
+    /// Builds the `FunctionSignature` of the function whose syntax node spans exactly `range`, if there is one.
+    pub fn function_signature(&self, range: TextRange) -> Option<FunctionSignature> {
+        self.parse
+            .tree()
+            .syntax()
+            .descendants()
+            .filter_map(ast::Fn::cast)
+            .find(|function| function.syntax().text_range() == range)
+            .map(|function| FunctionSignature::from_fn(&function))
+    }
+
+    /// Builds the `MacroDefinition` of the `macro_rules!` item whose syntax node spans exactly `range`, if there is one.
+    pub fn macro_definition(&self, range: TextRange) -> Option<MacroDefinition> {
+        self.parse
+            .tree()
+            .syntax()
+            .descendants()
+            .filter_map(ast::MacroRules::cast)
+            .find(|macro_rules| macro_rules.syntax().text_range() == range)
+            .map(|macro_rules| MacroDefinition::from_macro_rules(&macro_rules))
+    }
+
+    /// Returns the `EnclosingType` of the associated item whose syntax node spans exactly `range`, if there is one.
+    pub fn enclosing_type(&self, range: TextRange) -> Option<EnclosingType> {
+        EnclosingType::of_item(self.parse.tree().syntax(), range)
+    }
+
+    /// Builds the `ModuleContext` of the module enclosing the item whose syntax node spans exactly `range`, if there is one.
+    pub fn module_context(&self, range: TextRange) -> Option<ModuleContext> {
+        ModuleContext::of_item(self.parse.tree().syntax(), range)
+    }
+
+    /// Returns the syntax errors that overlap or touch `range`. An item with any of them was parsed from a recovered tree,
+    /// so its range may be wrong and it must not be edited.
+    pub fn syntax_errors_in(&self, range: TextRange) -> Vec<&SyntaxErrorRange> {
+        self.syntax_errors
+            .iter()
+            .filter(|error| range.intersect(error.span.text_range()).is_some())
+            .collect()
+    }
+
+    /// Returns the whole lines touched by `span`, so the first line keeps its indentation.
+    pub fn lines(&self, span: &Span) -> Option<String> {
+        let lines = span.lines();
+        let as_vec = self.source.lines().collect::<Vec<&str>>();
+        as_vec
+            .get(lines.start().checked_sub(1)?..*lines.end())
+            .map(|slice| slice.join("\n"))
+    }
+}
+
+/// Per-run cache of parsed files, keyed by path.
+/// A file is read and parsed once per run; later lookups are served from memory until the entry is invalidated,
+/// e.g. after patchdog wrote to the file.
+/// Editions are cached per directory, so manifests are not read again for every file.
+/// Every path gets one `FileId` for the whole run, which stays the same when the file is parsed again after a change.
+#[derive(Debug, Default)]
+pub struct ParseCache {
+    files: RwLock<HashMap<PathBuf, Arc<ParsedFile>>>,
+    editions: RwLock<HashMap<PathBuf, Edition>>,
+    file_ids: RwLock<HashMap<PathBuf, FileId>>,
+}
+
+impl ParseCache {
+    /// Returns the parsed representation of the file at `path`, reading and parsing it only the first time the path is seen
+    /// during the run, or the first time after it was invalidated.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the Rust source file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Arc<ParsedFile>, ErrorHandling>` with the shared parsed file, or an `ErrorHandling` if reading or parsing fails.
+    pub fn get(&self, path: &Path) -> Result<Arc<ParsedFile>, ErrorHandling> {
+        if let Some(parsed) = self
+            .files
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+        {
+            return Ok(Arc::clone(parsed));
+        }
+        let source = fs::read_to_string(path).context(InvalidIoOperationsSnafu { path })?;
+        let parsed = Arc::new(ParsedFile::new(
+            self.file_id(path),
+            path,
+            source,
+            self.edition(path),
+        )?);
+        self.files
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.to_path_buf(), Arc::clone(&parsed));
+        Ok(parsed)
+    }
+
+    /// Drops the cached parse of `path`, so the next `get` reads the file from disk again. Its `FileId` is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the Rust source file whose contents changed.
+    pub fn invalidate(&self, path: &Path) {
+        self.files
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(path);
+    }
+
+    /// Returns the `FileId` of `path`, handing out the next free one the first time the path is seen.
+    /// Ids start at 1, `FileId::default()` stays reserved for sources that weren't read from a file.
+    pub fn file_id(&self, path: &Path) -> FileId {
+        if let Some(file_id) = self
+            .file_ids
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+        {
+            return *file_id;
+        }
+        let mut file_ids = self
+            .file_ids
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let next = FileId(file_ids.len() as u32 + 1);
+        *file_ids.entry(path.to_path_buf()).or_insert(next)
+    }
+
+    /// Returns the edition of the package owning `path`. Files of one directory always share their package,
+    /// so the manifests are only read for the first file of each directory.
+    pub fn edition(&self, path: &Path) -> Edition {
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        if let Some(edition) = self
+            .editions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&directory)
+        {
+            return *edition;
+        }
+        let edition = edition_for_file(path);
+        self.editions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(directory, edition);
+        edition
+    }
+}
+
+fn content_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
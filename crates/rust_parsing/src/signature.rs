@@ -0,0 +1,186 @@
+use ra_ap_syntax::AstNode;
+use ra_ap_syntax::ast::{self, HasGenericParams, HasName, HasTypeBounds};
+use serde::{Deserialize, Serialize};
+
+/// Structured description of a function signature, sent to the LLM next to the function text.
+/// It gives reliable facts for the `# Arguments`, `# Returns` and `# Errors` sections, and is used afterwards
+/// to check the generated comment against the actual signature.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FunctionSignature {
+    pub qualifiers: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub self_kind: Option<String>,
+    pub generics: Vec<GenericToken>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub where_clause: Vec<String>,
+    pub fn_input: Vec<FnInputToken>,
+    pub fn_out: FnOutputToken,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FnInputToken {
+    pub input_name: String,
+    pub input_type: String,
+}
+
+/// Return type of a function. `kind` is `unit` when nothing is returned, `result` when the function returns a
+/// `Result`, in which case `output_type` is the ok type and `error_type` the error type, and `type` otherwise.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FnOutputToken {
+    pub kind: String,
+    pub output_type: String,
+    pub error_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GenericToken {
+    pub name: String,
+    pub bounds: Vec<String>,
+}
+
+impl FunctionSignature {
+    /// Builds the signature of a function from its syntax node: qualifiers, receiver, generic parameters with their bounds,
+    /// where-clause predicates, parameters and the return type, which is split into ok and error types for `Result`.
+    ///
+    /// # Arguments
+    ///
+    /// * `function` - The `ast::Fn` node to describe.
+    ///
+    /// # Returns
+    ///
+    /// A `FunctionSignature` describing the function.
+    pub fn from_fn(function: &ast::Fn) -> FunctionSignature {
+        let param_list = function.param_list();
+        FunctionSignature {
+            qualifiers: qualifiers(function),
+            self_kind: param_list
+                .as_ref()
+                .and_then(|params| params.self_param())
+                .map(|self_param| self_param.syntax().text().to_string()),
+            generics: function
+                .generic_param_list()
+                .map(|list| list.generic_params().map(generic_token).collect())
+                .unwrap_or_default(),
+            where_clause: function
+                .where_clause()
+                .map(|clause| clause.predicates().map(|pred| pred.to_string()).collect())
+                .unwrap_or_default(),
+            fn_input: param_list
+                .map(|params| params.params().map(input_token).collect())
+                .unwrap_or_default(),
+            fn_out: output_token(function.ret_type().and_then(|ret| ret.ty())),
+        }
+    }
+
+    /// Lists the facts of the signature a generated comment fails to mention: parameters that are never named,
+    /// and a missing `# Errors` section for functions returning `Result`.
+    ///
+    /// # Arguments
+    ///
+    /// * `comment` - The generated documentation comment.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<String>` with one entry per missing fact, empty when the comment covers the whole signature.
+    pub fn missing_in_comment(&self, comment: &str) -> Vec<String> {
+        let mut missing = self
+            .fn_input
+            .iter()
+            .filter(|input| !input.input_name.is_empty() && input.input_name != "_")
+            .filter(|input| !comment.contains(&input.input_name))
+            .map(|input| format!("argument `{}`", input.input_name))
+            .collect::<Vec<String>>();
+        if self.fn_out.kind == "result" && !comment.contains("# Errors") {
+            missing.push("# Errors section".to_string());
+        }
+        missing
+    }
+}
+
+fn qualifiers(function: &ast::Fn) -> Vec<String> {
+    let mut qualifiers = vec![];
+    if function.const_token().is_some() {
+        qualifiers.push("const".to_string());
+    }
+    if function.async_token().is_some() {
+        qualifiers.push("async".to_string());
+    }
+    if function.unsafe_token().is_some() {
+        qualifiers.push("unsafe".to_string());
+    }
+    if let Some(abi) = function.abi() {
+        qualifiers.push(abi.to_string());
+    }
+    qualifiers
+}
+
+fn input_token(param: ast::Param) -> FnInputToken {
+    FnInputToken {
+        input_name: param.pat().map(|pat| pat.to_string()).unwrap_or_default(),
+        input_type: param.ty().map(|ty| ty.to_string()).unwrap_or_default(),
+    }
+}
+
+fn generic_token(param: ast::GenericParam) -> GenericToken {
+    match param {
+        ast::GenericParam::TypeParam(type_param) => GenericToken {
+            name: type_param
+                .name()
+                .map(|name| name.to_string())
+                .unwrap_or_default(),
+            bounds: bounds(type_param.type_bound_list()),
+        },
+        ast::GenericParam::LifetimeParam(lifetime_param) => GenericToken {
+            name: lifetime_param
+                .lifetime()
+                .map(|lifetime| lifetime.to_string())
+                .unwrap_or_default(),
+            bounds: bounds(lifetime_param.type_bound_list()),
+        },
+        ast::GenericParam::ConstParam(const_param) => GenericToken {
+            name: const_param.syntax().text().to_string(),
+            bounds: vec![],
+        },
+    }
+}
+
+fn bounds(list: Option<ast::TypeBoundList>) -> Vec<String> {
+    list.map(|list| list.bounds().map(|bound| bound.to_string()).collect())
+        .unwrap_or_default()
+}
+
+fn output_token(ty: Option<ast::Type>) -> FnOutputToken {
+    let Some(ty) = ty else {
+        return FnOutputToken {
+            kind: "unit".to_string(),
+            output_type: "()".to_string(),
+            error_type: None,
+        };
+    };
+    if let ast::Type::PathType(path_type) = &ty
+        && let Some(segment) = path_type.path().and_then(|path| path.segment())
+        && segment
+            .name_ref()
+            .is_some_and(|name| name.to_string() == "Result")
+    {
+        let mut args = segment
+            .generic_arg_list()
+            .map(|list| {
+                list.generic_args()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default()
+            .into_iter();
+        return FnOutputToken {
+            kind: "result".to_string(),
+            output_type: args.next().unwrap_or_else(|| "()".to_string()),
+            error_type: args.next(),
+        };
+    }
+    FnOutputToken {
+        kind: "type".to_string(),
+        output_type: ty.to_string(),
+        error_type: None,
+    }
+}
@@ -0,0 +1,42 @@
+use ra_ap_ide::{Edition, TextRange};
+use ra_ap_syntax::{AstNode, SourceFile, ast};
+
+/// Placeholder written instead of a function body in a skeleton.
+const ELIDED_BODY: &str = "{ ... }";
+
+/// Reduces the source of an item to its skeleton: attributes, doc comments and signatures are kept, while the body of
+/// every function, including the default methods of a trait, is replaced by `{ ... }`. Structs and enums keep their
+/// fields and variants, as they have no body to elide. Functions nested in a body disappear together with it.
+///
+/// # Arguments
+///
+/// * `src` - The source text of one or more items.
+/// * `edition` - The edition to parse the source with.
+///
+/// # Returns
+///
+/// A `String` with the skeleton of the items, identical to `src` when they contain no function bodies.
+pub fn skeleton(src: &str, edition: Edition) -> String {
+    let tree = SourceFile::parse(src, edition).tree();
+    let mut bodies: Vec<TextRange> = vec![];
+    for body in tree
+        .syntax()
+        .descendants()
+        .filter_map(ast::Fn::cast)
+        .filter_map(|function| function.body())
+    {
+        let range = body.syntax().text_range();
+        // descendants are visited in preorder, so an enclosing body is always recorded first
+        if !bodies.iter().any(|outer| outer.contains_range(range)) {
+            bodies.push(range);
+        }
+    }
+    let mut skeleton = src.to_string();
+    for range in bodies.iter().rev() {
+        skeleton.replace_range(
+            usize::from(range.start())..usize::from(range.end()),
+            ELIDED_BODY,
+        );
+    }
+    skeleton
+}
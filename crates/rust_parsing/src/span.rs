@@ -0,0 +1,19 @@
+use ra_ap_ide::{TextRange, TextSize};
+
+pub use git_parsing::span::{FileId, LineCol, LineIndex, Span};
+
+/// `rust-analyzer` view of a `Span`. Spans are declared in `git_parsing`, so patch hunks and parsed items share them,
+/// while the conversions to syntax ranges stay with the parser.
+pub trait SpanExt {
+    /// Converts the span back into the byte based `TextRange` used by `rust-analyzer`.
+    fn text_range(&self) -> TextRange;
+}
+
+impl SpanExt for Span {
+    fn text_range(&self) -> TextRange {
+        TextRange::new(
+            TextSize::from(self.bytes.start as u32),
+            TextSize::from(self.bytes.end as u32),
+        )
+    }
+}
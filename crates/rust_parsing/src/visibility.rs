@@ -0,0 +1,59 @@
+use ra_ap_syntax::{AstNode, SyntaxNode, ast};
+use serde::{Deserialize, Serialize};
+
+/// Declared visibility of an item, as written in the source.
+/// This is the syntactic visibility only, whether an item is actually reachable from outside of its crate
+/// also depends on the modules and re-exports leading to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Visibility {
+    Public,
+    Crate,
+    Super,
+    InPath(String),
+    #[default]
+    Private,
+}
+
+impl Visibility {
+    /// Reads the visibility modifier of an item node, items without one are `Private`.
+    /// A `macro_rules!` has no modifier, it is exported from its crate by `#[macro_export]` and is `Public` then.
+    pub fn of(node: &SyntaxNode) -> Visibility {
+        if ast::MacroRules::can_cast(node.kind()) {
+            let exported = node
+                .children()
+                .filter_map(ast::Attr::cast)
+                .filter_map(|attr| attr.path())
+                .any(|path| path.syntax().text() == "macro_export");
+            return if exported {
+                Visibility::Public
+            } else {
+                Visibility::Private
+            };
+        }
+        node.children()
+            .find_map(ast::Visibility::cast)
+            .map(|visibility| Visibility::from_modifier(&visibility))
+            .unwrap_or_default()
+    }
+
+    fn from_modifier(visibility: &ast::Visibility) -> Visibility {
+        let modifier = visibility
+            .syntax()
+            .text()
+            .to_string()
+            .split_whitespace()
+            .collect::<String>();
+        match modifier.as_str() {
+            "pub" => Visibility::Public,
+            "pub(crate)" | "crate" => Visibility::Crate,
+            "pub(super)" => Visibility::Super,
+            "pub(self)" => Visibility::Private,
+            _ => Visibility::InPath(
+                modifier
+                    .trim_start_matches("pub(in")
+                    .trim_end_matches(')')
+                    .to_string(),
+            ),
+        }
+    }
+}